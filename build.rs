@@ -1,3 +1,5 @@
 fn main() {
+    // 只有构建图形界面时才需要编译界面文件
+    #[cfg(feature = "app")]
     slint_build::compile("src/ui/MainWindow.slint").unwrap();
 }
@@ -0,0 +1,117 @@
+//! 比较逆波兰式的计算和字节码虚拟机对同一个公式计算很多组输入时的速度。
+//!
+//! 运行 `cargo run --release --example bytecode_bench -- [公式] [次数]` ，
+//! 公式中的变量 `x` 依次取 1 、2 、3 ……
+
+use bigdecimal::BigDecimal;
+use std::time::{Duration, Instant};
+use toy_parse::bytecode::{Bytecode, Vm};
+use toy_parse::calculate::calculate_with_options;
+use toy_parse::lower::lower;
+use toy_parse::parse::{parse, RPNItem};
+use toy_parse::token::tokenization;
+use toy_parse::token_render::TokenRender;
+use toy_parse::{BigRational, Env, EvalOptions, Numeric};
+
+/// 默认的公式
+const FORMULA: &str = "3*x^3 - 2*x^2 + x/7 - max(x, 100) + (x + 1) * (x - 1)";
+
+/// 默认的计算次数
+const COUNT: usize = 20000;
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let formula = args.first().map_or(FORMULA, |s| s.as_str());
+    let count = match args.get(1).map(|s| s.parse::<usize>()) {
+        Some(Ok(count)) => count,
+        Some(Err(_)) => {
+            eprintln!("计算次数必须是正整数");
+            std::process::exit(2);
+        }
+        None => COUNT,
+    };
+
+    let tokens = match tokenization(formula) {
+        Ok(tokens) => tokens,
+        Err(err) => {
+            eprintln!("词法分析出错: {:?}", err);
+            std::process::exit(1);
+        }
+    };
+    let exp = match parse(&mut TokenRender::new_with_tokens(tokens)) {
+        Ok(expr) => lower(&expr),
+        Err(err) => {
+            eprintln!("语法分析出错: {:?}", err);
+            std::process::exit(1);
+        }
+    };
+
+    println!("公式: {}", formula);
+    println!("计算次数: {}", count);
+    bench::<BigDecimal>(&exp, count);
+    bench::<BigRational>(&exp, count);
+    bench::<f64>(&exp, count);
+    bench::<i128>(&exp, count);
+}
+
+/// 用数字类型 `N` 分别计算 `count` 次，打印两种方式的耗时和每秒计算的次数
+fn bench<N: Numeric>(exp: &[RPNItem], count: usize) {
+    let options = EvalOptions::default();
+    let inputs: Vec<N> = (1..=count)
+        .filter_map(|x| N::from_decimal(&BigDecimal::from(x as u64)))
+        .collect();
+
+    // 逆波兰式的计算，每次把 x 放进计算环境
+    let start = Instant::now();
+    let mut env: Env<N> = Env::default();
+    let mut expected = vec![];
+    for x in &inputs {
+        env.set("x", x.clone());
+        expected.push(calculate_with_options(exp, &env, &options));
+    }
+    let rpn_time = start.elapsed();
+
+    // 字节码只编译一次，同一个虚拟机反复执行
+    let start = Instant::now();
+    let bytecode = match Bytecode::<N>::compile(exp) {
+        Ok(bytecode) => bytecode,
+        Err(err) => {
+            println!("{}: 编译出错，{}", N::NAME, err);
+            return;
+        }
+    };
+    let x_slot = bytecode.slots().iter().position(|name| name == "x");
+    if bytecode.slots().len() > x_slot.map_or(0, |_| 1) {
+        println!("{}: 公式中只能使用变量 x", N::NAME);
+        return;
+    }
+    let mut vm = Vm::new();
+    let mut actual = vec![];
+    for x in &inputs {
+        let slots = match x_slot {
+            Some(_) => std::slice::from_ref(x),
+            None => &[],
+        };
+        actual.push(vm.run(&bytecode, slots, &options));
+    }
+    let vm_time = start.elapsed();
+
+    assert!(
+        expected == actual,
+        "{}: 字节码的结果和逆波兰式不同",
+        N::NAME
+    );
+    println!(
+        "{:<12} 逆波兰式 {:>10.3?} ({:>10.0} 次/秒)  字节码 {:>10.3?} ({:>10.0} 次/秒)  快 {:.2} 倍",
+        N::NAME,
+        rpn_time,
+        throughput(inputs.len(), rpn_time),
+        vm_time,
+        throughput(inputs.len(), vm_time),
+        rpn_time.as_secs_f64() / vm_time.as_secs_f64()
+    );
+}
+
+fn throughput(count: usize, time: Duration) -> f64 {
+    return count as f64 / time.as_secs_f64();
+}
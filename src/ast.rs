@@ -0,0 +1,182 @@
+use crate::parse::Operator;
+use crate::token::Span;
+use bigdecimal::BigDecimal;
+
+type Num = BigDecimal;
+
+/**
+抽象语法树 AST 的节点。
+
+* `kind` 节点的具体内容
+* `span` 节点在输入串中对应的区间
+
+ */
+#[derive(Debug, Clone)]
+pub struct Expr {
+    /// 节点的具体内容
+    pub kind: ExprKind,
+    /// 节点在输入串中对应的区间，括号表达式包含两边的括号
+    pub span: Span,
+}
+
+/**
+AST 节点的具体内容。
+
+* `ExprKind::Number` 数字
+* `ExprKind::Variable` 变量
+* `ExprKind::Unary` 单目运算
+* `ExprKind::Binary` 双目运算
+* `ExprKind::Call` 函数调用
+
+ */
+#[derive(Debug, Clone)]
+pub enum ExprKind {
+    /// 数字
+    Number(Num),
+    /// 变量
+    Variable(String),
+    /// 单目运算，目前只有取负
+    Unary {
+        /// 运算符
+        op: Operator,
+        /// 运算符在输入串中的区间
+        op_span: Span,
+        /// 操作数
+        operand: Box<Expr>,
+    },
+    /// 双目运算
+    Binary {
+        /// 运算符
+        op: Operator,
+        /// 运算符在输入串中的区间
+        op_span: Span,
+        /// 左操作数
+        left: Box<Expr>,
+        /// 右操作数
+        right: Box<Expr>,
+    },
+    /// 函数调用
+    Call {
+        /// 函数名
+        name: String,
+        /// 函数名在输入串中的区间
+        name_span: Span,
+        /// 参数列表
+        args: Vec<Expr>,
+    },
+}
+
+impl Expr {
+    /** 用数字和它的区间初始化一个节点。 */
+    pub fn number(n: Num, span: Span) -> Expr {
+        return Expr {
+            kind: ExprKind::Number(n),
+            span,
+        };
+    }
+
+    /** 用变量名和它的区间初始化一个节点。 */
+    pub fn variable(name: String, span: Span) -> Expr {
+        return Expr {
+            kind: ExprKind::Variable(name),
+            span,
+        };
+    }
+
+    /** 初始化一个单目运算节点，区间从运算符开始到操作数结束。 */
+    pub fn unary(op: Operator, op_span: Span, operand: Expr) -> Expr {
+        let span = op_span.to(operand.span);
+        return Expr {
+            kind: ExprKind::Unary {
+                op,
+                op_span,
+                operand: Box::new(operand),
+            },
+            span,
+        };
+    }
+
+    /** 初始化一个双目运算节点，区间从左操作数开始到右操作数结束。 */
+    pub fn binary(op: Operator, op_span: Span, left: Expr, right: Expr) -> Expr {
+        let span = left.span.to(right.span);
+        return Expr {
+            kind: ExprKind::Binary {
+                op,
+                op_span,
+                left: Box::new(left),
+                right: Box::new(right),
+            },
+            span,
+        };
+    }
+
+    /** 初始化一个函数调用节点，`span` 从函数名开始到右括号结束。 */
+    pub fn call(name: String, name_span: Span, args: Vec<Expr>, span: Span) -> Expr {
+        return Expr {
+            kind: ExprKind::Call {
+                name,
+                name_span,
+                args,
+            },
+            span,
+        };
+    }
+}
+
+/**
+语句。
+
+* `kind` 语句的具体内容
+* `span` 语句在输入串中对应的区间
+
+ */
+#[derive(Debug, Clone)]
+pub struct Stmt {
+    /// 语句的具体内容
+    pub kind: StmtKind,
+    /// 语句在输入串中对应的区间
+    pub span: Span,
+}
+
+/**
+语句的具体内容。
+
+* `StmtKind::Let` 定义变量，如 `let x = 1`
+* `StmtKind::Assign` 给已经定义的变量赋值，如 `x = 2`
+* `StmtKind::Expr` 单独的表达式
+
+ */
+#[derive(Debug, Clone)]
+pub enum StmtKind {
+    /// 定义变量，变量已经存在时覆盖原来的值
+    Let {
+        /// 变量名
+        name: String,
+        /// 变量名在输入串中的区间
+        name_span: Span,
+        /// 变量的值
+        value: Expr,
+    },
+    /// 给已经定义的变量赋值
+    Assign {
+        /// 变量名
+        name: String,
+        /// 变量名在输入串中的区间
+        name_span: Span,
+        /// 变量的值
+        value: Expr,
+    },
+    /// 单独的表达式
+    Expr(Expr),
+}
+
+impl Stmt {
+    /** 语句中需要计算的表达式。 */
+    pub fn value(&self) -> &Expr {
+        match &self.kind {
+            StmtKind::Let { value, .. } => value,
+            StmtKind::Assign { value, .. } => value,
+            StmtKind::Expr(value) => value,
+        }
+    }
+}
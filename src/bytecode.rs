@@ -0,0 +1,496 @@
+use crate::calculate::{
+    apply_binary, call_function, find_function, from_decimal, negate, CalcError, Env,
+};
+use crate::function::Function;
+use crate::numeric::Numeric;
+use crate::options::EvalOptions;
+use crate::parse::{Operator, RPNItem};
+use crate::token::Span;
+use bigdecimal::BigDecimal;
+use std::fmt;
+
+type Num = BigDecimal;
+
+/**
+字节码中的一条指令。
+
+* `OpCode::Const` 把常量池中的第几个常量压入运算栈
+* `OpCode::Load` 把第几个变量槽的值压入运算栈
+* `OpCode::Binary` 取出栈顶的两个数字做双目运算
+* `OpCode::BinaryConst` 栈顶和常量池中的常量做双目运算，常量是右边的运算对象
+* `OpCode::BinaryLoad` 栈顶和变量槽的值做双目运算，变量是右边的运算对象
+* `OpCode::Negate` 栈顶取负
+* `OpCode::Call` 调用函数表中的第几个函数，从运算栈中取出 `arity` 个参数
+
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpCode {
+    /// 压入常量池中的常量
+    Const(usize),
+    /// 压入变量槽的值
+    Load(usize),
+    /// 双目运算
+    Binary(Operator),
+    /// 栈顶和常量做双目运算
+    BinaryConst {
+        /// 运算符
+        op: Operator,
+        /// 常量在常量池中的序号
+        constant: usize,
+    },
+    /// 栈顶和变量做双目运算
+    BinaryLoad {
+        /// 运算符
+        op: Operator,
+        /// 变量槽的序号
+        slot: usize,
+    },
+    /// 取负
+    Negate,
+    /// 函数调用
+    Call {
+        /// 函数在函数表中的序号
+        function: usize,
+        /// 参数个数
+        arity: usize,
+    },
+}
+
+/**
+逆波兰式编译得到的字节码。
+
+数字在编译时转换为计算使用的数字类型放进常量池，变量按第一次出现的顺序分配变量槽，
+函数在编译时查好并检查参数个数，运算栈的深度也在编译时检查，所以执行时不用再查表和检查栈。
+
+* `code` 指令
+* `spans` 每条指令在输入串中的区间，出错时指出这个位置
+* `constants` 常量池
+* `constant_texts` 常量池中每个常量在逆波兰式中的写法
+* `slots` 每个变量槽对应的变量名
+* `slot_spans` 每个变量第一次出现的区间
+* `functions` 函数表，函数名和函数
+* `max_depth` 执行时运算栈的最大深度
+
+ */
+#[derive(Clone)]
+pub struct Bytecode<N = Num> {
+    /// 指令
+    code: Vec<OpCode>,
+    /// 每条指令在输入串中的区间
+    spans: Vec<Span>,
+    /// 常量池
+    constants: Vec<N>,
+    /// 常量池中每个常量在逆波兰式中的写法
+    constant_texts: Vec<String>,
+    /// 每个变量槽对应的变量名
+    slots: Vec<String>,
+    /// 每个变量第一次出现的区间
+    slot_spans: Vec<Span>,
+    /// 函数表
+    functions: Vec<(String, &'static Function)>,
+    /// 执行时运算栈的最大深度
+    max_depth: usize,
+}
+
+impl<N: Numeric> Bytecode<N> {
+    /**
+    把逆波兰式编译为字节码。
+
+    数字不能用 `N` 表示、函数不存在、参数个数不对和逆波兰式不完整的错误在编译时报告，
+    所以有多处错误时报告的可能和逆波兰式的计算不是同一处。
+
+    * `exp` 输入的逆波兰式数组
+     */
+    pub fn compile(exp: &[RPNItem]) -> Result<Bytecode<N>, CalcError> {
+        let mut bytecode = Bytecode {
+            code: vec![],
+            spans: vec![],
+            constants: vec![],
+            constant_texts: vec![],
+            slots: vec![],
+            slot_spans: vec![],
+            functions: vec![],
+            max_depth: 0,
+        };
+        // 编译到当前位置时运算栈的深度
+        let mut depth = 0;
+
+        for item in exp {
+            let (op, span, pops) = match item {
+                RPNItem::Number { value, span } => {
+                    let index = bytecode.constant(value, *span)?;
+                    (OpCode::Const(index), *span, 0)
+                }
+                RPNItem::Variable { name, span } => {
+                    (OpCode::Load(bytecode.slot_for(name, *span)), *span, 0)
+                }
+                RPNItem::Operator {
+                    op: Operator::Minus,
+                    span,
+                } => (OpCode::Negate, *span, 1),
+                RPNItem::Operator { op, span } => (OpCode::Binary(*op), *span, 2),
+                RPNItem::Call { name, arity, span } => {
+                    let function = find_function(name, *arity, *span)?;
+                    bytecode.functions.push((name.clone(), function));
+                    let op = OpCode::Call {
+                        function: bytecode.functions.len() - 1,
+                        arity: *arity,
+                    };
+                    (op, *span, *arity)
+                }
+            };
+
+            if depth < pops {
+                // 如果运行到这里了，证明输入的逆波兰式有问题，应该排查上一步的语法分析。
+
+                return Err(CalcError::StackUnderflow(span));
+            }
+            depth = depth - pops + 1;
+            bytecode.max_depth = bytecode.max_depth.max(depth);
+            bytecode.push(op, span);
+        }
+
+        if depth != 1 {
+            return Err(CalcError::LeftoverOperands(depth));
+        }
+        return Ok(bytecode);
+    }
+
+    /// 追加一条指令，右边的运算对象是常量或者变量时和前一条指令合并
+    fn push(&mut self, op: OpCode, span: Span) {
+        if let OpCode::Binary(operator) = op {
+            let fused = match self.code.last() {
+                Some(OpCode::Const(constant)) => Some(OpCode::BinaryConst {
+                    op: operator,
+                    constant: *constant,
+                }),
+                Some(OpCode::Load(slot)) => Some(OpCode::BinaryLoad {
+                    op: operator,
+                    slot: *slot,
+                }),
+                _ => None,
+            };
+            if let Some(fused) = fused {
+                // 合并后出错时指出运算符
+                *self.code.last_mut().unwrap() = fused;
+                *self.spans.last_mut().unwrap() = span;
+                return;
+            }
+        }
+        self.code.push(op);
+        self.spans.push(span);
+    }
+
+    /// 把常量转换为数字类型 `N` 后放进常量池，相同的常量只放一次
+    fn constant(&mut self, value: &Num, span: Span) -> Result<usize, CalcError> {
+        let converted = from_decimal(value, span)?;
+        if let Some(index) = self.constants.iter().position(|n| *n == converted) {
+            return Ok(index);
+        }
+        self.constants.push(converted);
+        self.constant_texts.push(value.normalized().to_string());
+        return Ok(self.constants.len() - 1);
+    }
+
+    /// 变量对应的变量槽，第一次出现时分配新的变量槽
+    fn slot_for(&mut self, name: &str, span: Span) -> usize {
+        if let Some(index) = self.slots.iter().position(|slot| slot == name) {
+            return index;
+        }
+        self.slots.push(name.to_owned());
+        self.slot_spans.push(span);
+        return self.slots.len() - 1;
+    }
+
+    /** 所有指令。 */
+    pub fn code(&self) -> &[OpCode] {
+        return &self.code;
+    }
+
+    /** 常量池。 */
+    pub fn constants(&self) -> &[N] {
+        return &self.constants;
+    }
+
+    /** 每个变量槽对应的变量名，执行时按这个顺序传入变量的值。 */
+    pub fn slots(&self) -> &[String] {
+        return &self.slots;
+    }
+
+    /**
+    从计算环境中按变量槽的顺序取出变量的值。
+
+    环境中没有某个变量时返回 `CalcError::UnknownVariable` ，指出这个变量第一次出现的位置。
+     */
+    pub fn bind(&self, env: &Env<N>) -> Result<Vec<N>, CalcError> {
+        return self
+            .slots
+            .iter()
+            .zip(&self.slot_spans)
+            .map(|(name, span)| {
+                env.get(name)
+                    .cloned()
+                    .ok_or_else(|| CalcError::UnknownVariable {
+                        name: name.clone(),
+                        span: *span,
+                    })
+            })
+            .collect();
+    }
+}
+
+impl<N> fmt::Display for Bytecode<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let constant = |index: usize| &self.constant_texts[index];
+        for (index, op) in self.code.iter().enumerate() {
+            let text = match op {
+                OpCode::Const(n) => format!("const {}", constant(*n)),
+                OpCode::Load(n) => format!("load {}", self.slots[*n]),
+                OpCode::Binary(op) => format!("{}", op),
+                OpCode::BinaryConst { op, constant: n } => format!("{} const {}", op, constant(*n)),
+                OpCode::BinaryLoad { op, slot } => format!("{} load {}", op, self.slots[*slot]),
+                OpCode::Negate => "@".to_owned(),
+                OpCode::Call { function, arity } => {
+                    format!("call {}:{}", self.functions[*function].0, arity)
+                }
+            };
+            if index > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}: {}", index, text)?;
+        }
+        return Ok(());
+    }
+}
+
+/**
+执行字节码的虚拟机。
+
+虚拟机保留运算栈，反复执行同一段字节码时不用重新分配运算栈，适合对很多组输入计算同一个公式。
+ */
+pub struct Vm<N = Num> {
+    /// 运算栈
+    stack: Vec<N>,
+}
+
+impl<N: Numeric> Default for Vm<N> {
+    fn default() -> Vm<N> {
+        return Vm { stack: vec![] };
+    }
+}
+
+impl<N: Numeric> Vm<N> {
+    /** 初始化一个运算栈为空的虚拟机。 */
+    pub fn new() -> Vm<N> {
+        return Vm::default();
+    }
+
+    /**
+    执行字节码，返回算出的值。
+
+    每个运算和逆波兰式的计算共用同一份实现，所以能算出结果时和
+    [`crate::calculate::calculate_with_options`] 的结果相同。
+
+    * `bytecode` [`Bytecode::compile`] 编译得到的字节码
+    * `slots` 按 [`Bytecode::slots`] 的顺序排列的变量的值，个数不对时 panic
+    * `options` 计算的选项
+     */
+    pub fn run(
+        &mut self,
+        bytecode: &Bytecode<N>,
+        slots: &[N],
+        options: &EvalOptions,
+    ) -> Result<N, CalcError> {
+        assert_eq!(
+            slots.len(),
+            bytecode.slots.len(),
+            "变量的个数和变量槽不一致"
+        );
+
+        let stack = &mut self.stack;
+        stack.clear();
+        stack.reserve(bytecode.max_depth);
+
+        // 编译时已经检查过运算栈的深度，下面取出数字时不会遇到空栈
+        for (op, span) in bytecode.code.iter().zip(&bytecode.spans) {
+            match *op {
+                OpCode::Const(n) => stack.push(bytecode.constants[n].clone()),
+                OpCode::Load(n) => stack.push(slots[n].clone()),
+                OpCode::Binary(op) => {
+                    let right = stack.pop().unwrap();
+                    let left = stack.last_mut().unwrap();
+                    *left = apply_binary(op, left, &right, *span, options)?;
+                }
+                OpCode::BinaryConst { op, constant } => {
+                    let left = stack.last_mut().unwrap();
+                    *left = apply_binary(op, left, &bytecode.constants[constant], *span, options)?;
+                }
+                OpCode::BinaryLoad { op, slot } => {
+                    let left = stack.last_mut().unwrap();
+                    *left = apply_binary(op, left, &slots[slot], *span, options)?;
+                }
+                OpCode::Negate => {
+                    let top = stack.last_mut().unwrap();
+                    *top = negate(top, *span)?;
+                }
+                OpCode::Call { function, arity } => {
+                    let (name, function) = &bytecode.functions[function];
+                    let start = stack.len() - arity;
+                    let result = call_function(name, function, &stack[start..], *span, options)?;
+                    stack.truncate(start);
+                    stack.push(result);
+                }
+            }
+        }
+        return Ok(stack.pop().unwrap());
+    }
+}
+
+/**
+编译并执行一次逆波兰式，变量从计算环境中取值。
+
+对很多组输入计算同一个公式时应该只编译一次，再用同一个 [`Vm`] 反复执行。
+
+* `exp` 输入的逆波兰式数组
+* `env` 计算环境，变量从这里取值
+* `options` 计算的选项
+ */
+pub fn calculate_bytecode<N: Numeric>(
+    exp: &[RPNItem],
+    env: &Env<N>,
+    options: &EvalOptions,
+) -> Result<N, CalcError> {
+    let bytecode = Bytecode::compile(exp)?;
+    let slots = bytecode.bind(env)?;
+    return Vm::new().run(&bytecode, &slots, options);
+}
+
+#[cfg(test)]
+mod test {
+    use super::{calculate_bytecode, Bytecode, OpCode, Vm};
+    use crate::calculate::{calculate_with_options, CalcError, Env};
+    use crate::lower::lower;
+    use crate::numeric::Numeric;
+    use crate::options::EvalOptions;
+    use crate::parse::{parse, Operator, RPNItem};
+    use crate::token::{tokenization, Span};
+    use crate::token_render::*;
+    use bigdecimal::BigDecimal;
+    use num_rational::BigRational;
+
+    fn rpn(input: &str) -> Vec<RPNItem> {
+        let tokens = tokenization(input).unwrap();
+        return lower(&parse(&mut TokenRender::new_with_tokens(tokens)).unwrap());
+    }
+
+    #[test]
+    fn compile_test() {
+        // 测试常量池、变量槽和合并的指令
+        let bytecode = Bytecode::<BigDecimal>::compile(&rpn("x*x + 2*x - 2")).unwrap();
+        assert_eq!(
+            bytecode.code(),
+            [
+                OpCode::Load(0),
+                OpCode::BinaryLoad {
+                    op: Operator::Mul,
+                    slot: 0
+                },
+                OpCode::Const(0),
+                OpCode::BinaryLoad {
+                    op: Operator::Mul,
+                    slot: 0
+                },
+                OpCode::Binary(Operator::Add),
+                OpCode::BinaryConst {
+                    op: Operator::Sub,
+                    constant: 0
+                },
+            ]
+        );
+        assert_eq!(bytecode.slots(), ["x"]);
+        assert_eq!(bytecode.constants(), [BigDecimal::from(2)]);
+
+        let bytecode = Bytecode::<BigDecimal>::compile(&rpn("-max(y, 1) / 3")).unwrap();
+        assert_eq!(
+            bytecode.to_string(),
+            "0: load y\n1: const 1\n2: call max:2\n3: @\n4: / const 3"
+        );
+    }
+
+    fn same_as_rpn<N: Numeric>(input: &str, options: &EvalOptions) {
+        let exp = rpn(input);
+        let mut env: Env<N> = Env::default();
+        env.set("x", N::from_decimal(&BigDecimal::from(3)).unwrap());
+        env.set("y", N::from_decimal(&BigDecimal::from(-4)).unwrap());
+        assert_eq!(
+            calculate_bytecode(&exp, &env, options),
+            calculate_with_options(&exp, &env, options),
+            "{}: {}",
+            N::NAME,
+            input
+        );
+    }
+
+    #[test]
+    fn calculate_bytecode_test() {
+        // 测试字节码的结果和逆波兰式的计算相同，包括出错的位置
+        let input_vec = vec![
+            "x*x + 2*x - 2",
+            "-x ^ 2 * y // 3 % 2",
+            "1/3 + max(x, y, 2) - sqrt(x + 1)",
+            "(-(x - y)) * (x / y)",
+            "x / (y + 4)",
+            "2 ^ 0.5",
+            "sqrt(y)",
+            "z + 1",
+        ];
+        let options = EvalOptions::default();
+        for input in input_vec {
+            same_as_rpn::<BigDecimal>(input, &options);
+            same_as_rpn::<BigRational>(input, &options);
+            same_as_rpn::<f64>(input, &options);
+            same_as_rpn::<i128>(input, &options);
+        }
+    }
+
+    #[test]
+    fn vm_reuse_test() {
+        // 测试同一个虚拟机反复执行，变量按变量槽的顺序传入
+        let bytecode = Bytecode::<i128>::compile(&rpn("a * 10 + b")).unwrap();
+        assert_eq!(bytecode.slots(), ["a", "b"]);
+        let options = EvalOptions::default();
+        let mut vm = Vm::new();
+        for a in 0..5 {
+            let result = vm.run(&bytecode, &[a, 7], &options);
+            assert_eq!(result, Ok(a * 10 + 7));
+        }
+        let result = vm.run(&bytecode, &[1, 0], &options);
+        assert_eq!(result, Ok(10));
+    }
+
+    #[test]
+    fn compile_test_fail() {
+        // 测试编译时报告的错误
+        let items = rpn("1 + foo(1)");
+        let result = Bytecode::<BigDecimal>::compile(&items);
+        assert!(matches!(result, Err(CalcError::UnknownFunction { .. })));
+
+        let items = rpn("max()");
+        let result = Bytecode::<BigDecimal>::compile(&items);
+        assert!(matches!(result, Err(CalcError::ArityMismatch { .. })));
+
+        let result = Bytecode::<i128>::compile(&rpn("0.5 + 1"));
+        assert!(matches!(result, Err(CalcError::NotRepresentable { .. })));
+
+        let span = Span::new(0, 1);
+        let items = vec![RPNItem::Operator {
+            op: Operator::Add,
+            span,
+        }];
+        let result = Bytecode::<BigDecimal>::compile(&items);
+        assert!(matches!(result, Err(CalcError::StackUnderflow(s)) if s == span));
+
+        let result = Bytecode::<BigDecimal>::compile(&[]);
+        assert!(matches!(result, Err(CalcError::LeftoverOperands(0))));
+    }
+}
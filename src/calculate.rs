@@ -1,73 +1,441 @@
+use crate::function::{Function, FUNCTION_LIST};
+use crate::numeric::Numeric;
+use crate::options::EvalOptions;
 use crate::parse::{self, RPNItem};
-use bigdecimal::{BigDecimal, Zero};
-use std::collections::VecDeque;
+use crate::token::Span;
+use bigdecimal::BigDecimal;
+use std::collections::{BTreeMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
 
 type Num = BigDecimal;
 
+/// 乘方运算允许的最大指数绝对值，避免算出天文数字卡死程序
+const MAX_EXPONENT: i64 = 100000;
+
+/**
+计算阶段的错误。
+
+* `CalcError::DivisionByZero` 除数为 0
+* `CalcError::InvalidExponent` 乘方的指数不合法
+* `CalcError::UnknownVariable` 环境中没有这个变量
+* `CalcError::UndefinedAssignment` 给没有定义的变量赋值
+* `CalcError::UnknownFunction` 没有这个函数
+* `CalcError::ArityMismatch` 函数的参数个数不对
+* `CalcError::DomainError` 参数超出函数的定义域
+* `CalcError::Overflow` 运算结果超出数字类型的范围
+* `CalcError::NotRepresentable` 数字类型不能表示这个数
+* `CalcError::StackUnderflow` 运算栈中的数字不够用
+* `CalcError::LeftoverOperands` 计算结束后运算栈中剩下不止一个数字
+
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum CalcError {
+    /// 除数为 0 ，附带运算符在输入串中的区间，0 作负数次乘方也算除 0
+    DivisionByZero(Span),
+    /// 乘方的指数不是整数或者绝对值太大
+    InvalidExponent {
+        /// 错误原因
+        reason: String,
+        /// 乘方运算符在输入串中的区间
+        span: Span,
+    },
+    /// 环境中没有这个变量
+    UnknownVariable {
+        /// 变量名
+        name: String,
+        /// 变量在输入串中的区间
+        span: Span,
+    },
+    /// 给没有定义的变量赋值，定义变量需要使用 let
+    UndefinedAssignment {
+        /// 变量名
+        name: String,
+        /// 变量名在输入串中的区间
+        span: Span,
+    },
+    /// 没有这个函数
+    UnknownFunction {
+        /// 函数名
+        name: String,
+        /// 函数调用在输入串中的区间
+        span: Span,
+    },
+    /// 函数的参数个数不对
+    ArityMismatch {
+        /// 函数名
+        name: String,
+        /// 函数接受的参数个数的说明，如 “1 个” 、“至少 1 个”
+        expected: String,
+        /// 实际的参数个数
+        found: usize,
+        /// 函数调用在输入串中的区间
+        span: Span,
+    },
+    /// 参数超出函数的定义域，如 `sqrt(-1)` 、`ln(0)`
+    DomainError {
+        /// 函数名
+        function: String,
+        /// 错误原因
+        reason: String,
+        /// 函数调用在输入串中的区间
+        span: Span,
+    },
+    /// 运算结果超出数字类型的范围，如 `i128` 溢出，附带运算符或函数调用在输入串中的区间
+    Overflow(Span),
+    /// 数字类型不能表示这个数，如 `i128` 不能表示小数
+    NotRepresentable {
+        /// 这个数的十进制形式
+        value: String,
+        /// 数字类型的名字
+        kind: &'static str,
+        /// 数字或函数调用在输入串中的区间
+        span: Span,
+    },
+    /// 运算栈中的数字不够用，说明逆波兰式有问题，附带取数字的元素在输入串中的区间
+    StackUnderflow(Span),
+    /// 计算结束后运算栈中剩下的数字个数不是 1 ，说明逆波兰式有问题
+    LeftoverOperands(usize),
+}
+
+impl CalcError {
+    /** 出错的位置，计算结束后运算栈中剩下的数字不对时没有位置。 */
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            CalcError::DivisionByZero(span) => Some(*span),
+            CalcError::InvalidExponent { span, .. } => Some(*span),
+            CalcError::UnknownVariable { span, .. } => Some(*span),
+            CalcError::UndefinedAssignment { span, .. } => Some(*span),
+            CalcError::UnknownFunction { span, .. } => Some(*span),
+            CalcError::ArityMismatch { span, .. } => Some(*span),
+            CalcError::DomainError { span, .. } => Some(*span),
+            CalcError::Overflow(span) => Some(*span),
+            CalcError::NotRepresentable { span, .. } => Some(*span),
+            CalcError::StackUnderflow(span) => Some(*span),
+            CalcError::LeftoverOperands(_) => None,
+        }
+    }
+
+    /** 标注在出错位置的说明。 */
+    pub fn label(&self) -> String {
+        match self {
+            CalcError::DivisionByZero(_) => "除数为 0".to_owned(),
+            CalcError::InvalidExponent { .. } => "这个乘方的指数不合法".to_owned(),
+            CalcError::UnknownVariable { .. } => "这个变量没有定义".to_owned(),
+            CalcError::UndefinedAssignment { .. } => "这个变量没有定义".to_owned(),
+            CalcError::UnknownFunction { .. } => "没有这个函数".to_owned(),
+            CalcError::ArityMismatch { found, .. } => format!("这里有 {} 个参数", found),
+            CalcError::DomainError { .. } => "参数超出了函数的定义域".to_owned(),
+            CalcError::Overflow(_) => "这里的运算结果超出了范围".to_owned(),
+            CalcError::NotRepresentable { kind, .. } => format!("这个数不能用 {} 表示", kind),
+            _ => self.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for CalcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CalcError::DivisionByZero(_) => write!(f, "出现除 0 错误"),
+            CalcError::InvalidExponent { reason, .. } => write!(f, "{}", reason),
+            CalcError::UnknownVariable { name, .. } => write!(f, "未定义的变量 {}", name),
+            CalcError::UndefinedAssignment { name, .. } => {
+                write!(f, "未定义的变量 {}，定义变量需要使用 let", name)
+            }
+            CalcError::UnknownFunction { name, .. } => write!(f, "未知的函数 {}", name),
+            CalcError::ArityMismatch {
+                name,
+                expected,
+                found,
+                ..
+            } => write!(
+                f,
+                "函数 {} 需要 {}参数，却得到了 {} 个",
+                name, expected, found
+            ),
+            CalcError::DomainError { reason, .. } => write!(f, "{}", reason),
+            CalcError::Overflow(_) => write!(f, "运算结果超出了数字类型的范围"),
+            CalcError::NotRepresentable { value, kind, .. } => {
+                write!(f, "{} 不能用 {} 表示", value, kind)
+            }
+            CalcError::StackUnderflow(_) => write!(f, "出现未知错误！栈内数字数量不符"),
+            CalcError::LeftoverOperands(_) => {
+                write!(f, "出现未知错误，运算栈里面剩余的数字不对")
+            }
+        }
+    }
+}
+
+impl Error for CalcError {}
+
+/**
+计算环境，储存变量名和值的对应关系。
+
+`N` 是变量的数字类型，和计算时使用的数字类型一致。
+ */
+#[derive(Debug, Clone)]
+pub struct Env<N = Num> {
+    vars: BTreeMap<String, N>,
+}
+
+impl<N> Default for Env<N> {
+    fn default() -> Env<N> {
+        return Env {
+            vars: BTreeMap::new(),
+        };
+    }
+}
+
+impl Env {
+    /** 初始化一个空的、使用十进制数的计算环境，其他数字类型使用 `Env::default()` 。 */
+    pub fn new() -> Env {
+        return Env::default();
+    }
+}
+
+impl<N> Env<N> {
+    /** 取出变量的值，没有这个变量时返回 `None` 。 */
+    pub fn get(&self, name: &str) -> Option<&N> {
+        return self.vars.get(name);
+    }
+
+    /** 设置变量的值，变量已经存在时会覆盖原来的值。 */
+    pub fn set(&mut self, name: &str, value: N) {
+        self.vars.insert(name.to_owned(), value);
+    }
+
+    /** 按变量名排序遍历所有变量。 */
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &N)> {
+        return self.vars.iter();
+    }
+}
+
+/**
+计算过程中的一步，由 [`calculate_traced`] 记录。
+
+* `item` 这一步执行的逆波兰式元素
+* `before` 执行前的运算栈，栈底在前
+* `after` 执行后的运算栈，栈底在前
+* `value` 这一步算出的值，也就是执行后的栈顶
+
+ */
+#[derive(Debug, Clone)]
+pub struct EvalStep<N = Num> {
+    /// 这一步执行的逆波兰式元素
+    pub item: RPNItem,
+    /// 执行前的运算栈，栈底在前
+    pub before: Vec<N>,
+    /// 执行后的运算栈，栈底在前
+    pub after: Vec<N>,
+    /// 这一步算出的值
+    pub value: N,
+}
+
+/**
+整除和取余运算对负数的处理方式。
+
+* `DivisionMode::Floored` 商向负无穷取整，余数和除数同号，如 `-7 // 2 = -4` ，`-7 % 2 = 1`
+* `DivisionMode::Truncated` 商向 0 取整，余数和被除数同号，如 `-7 // 2 = -3` ，`-7 % 2 = -1`
+
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DivisionMode {
+    /// 商向负无穷取整
+    Floored,
+    /// 商向 0 取整
+    Truncated,
+}
+
+impl DivisionMode {
+    /** 所有的整除方式，图形界面的下拉框按这个顺序排列。 */
+    pub const ALL: [DivisionMode; 2] = [DivisionMode::Floored, DivisionMode::Truncated];
+}
+
+impl fmt::Display for DivisionMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DivisionMode::Floored => write!(f, "floored"),
+            DivisionMode::Truncated => write!(f, "truncated"),
+        }
+    }
+}
+
+impl FromStr for DivisionMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<DivisionMode, String> {
+        return DivisionMode::ALL
+            .iter()
+            .find(|mode| mode.to_string() == s)
+            .copied()
+            .ok_or_else(|| format!("未知的整除方式 {}，可用的有 floored 、truncated", s));
+    }
+}
+
+/// 检查除数是否为 0 ，除法、整除和取余共用，`span` 是运算符的区间
+fn check_divisor<N: Numeric>(divisor: &N, span: Span) -> Result<(), CalcError> {
+    if divisor.is_zero() {
+        return Err(CalcError::DivisionByZero(span));
+    }
+    return Ok(());
+}
+
+/**
+乘方运算。
+
+只支持整数指数，整数指数的结果是精确的；负指数会先算出正指数的结果再取倒数。
+
+* `base` 底数
+* `exponent` 指数
+* `span` 乘方运算符在输入串中的区间
+* `options` 取倒数时除法的精度和舍入方式
+ */
+fn power<N: Numeric>(
+    base: &N,
+    exponent: &N,
+    span: Span,
+    options: &EvalOptions,
+) -> Result<N, CalcError> {
+    if !exponent.is_integer() {
+        return Err(CalcError::InvalidExponent {
+            reason: format!(
+                "乘方的指数必须为整数，却得到了 {}",
+                exponent.format(options)
+            ),
+            span,
+        });
+    }
+
+    let n = match exponent.to_i64() {
+        Some(n) if n.abs() <= MAX_EXPONENT => n,
+        _ => {
+            return Err(CalcError::InvalidExponent {
+                reason: format!("乘方的指数绝对值不能超过 {}", MAX_EXPONENT),
+                span,
+            });
+        }
+    };
+
+    if n < 0 && base.is_zero() {
+        // 0 不能作负数次乘方
+        return Err(CalcError::DivisionByZero(span));
+    }
+
+    return base
+        .checked_powi(n, options)
+        .ok_or(CalcError::Overflow(span));
+}
+
+/// 把十进制数转换为计算使用的数字类型，`span` 是数字或函数调用的区间
+pub(crate) fn from_decimal<N: Numeric>(n: &Num, span: Span) -> Result<N, CalcError> {
+    return N::from_decimal(n).ok_or_else(|| CalcError::NotRepresentable {
+        value: n.normalized().to_string(),
+        kind: N::NAME,
+        span,
+    });
+}
+
+/**
+计算函数，使用空的计算环境和默认的选项。
+
+* `exp` 输入的逆波兰式数组
+ */
+pub fn calculate(exp: &[RPNItem]) -> Result<Num, CalcError> {
+    return calculate_with(exp, &Env::new());
+}
+
+/**
+计算函数，使用默认的选项。
+
+* `exp` 输入的逆波兰式数组
+* `env` 计算环境，变量从这里取值
+ */
+pub fn calculate_with(exp: &[RPNItem], env: &Env) -> Result<Num, CalcError> {
+    return calculate_with_options(exp, env, &EvalOptions::default());
+}
+
+/**
+计算函数，除了整除和取余对负数的处理方式以外使用默认的选项。
+
+* `exp` 输入的逆波兰式数组
+* `env` 计算环境，变量从这里取值
+* `mode` 整除和取余对负数的处理方式
+ */
+pub fn calculate_with_mode(
+    exp: &[RPNItem],
+    env: &Env,
+    mode: DivisionMode,
+) -> Result<Num, CalcError> {
+    let options = EvalOptions {
+        division_mode: mode,
+        ..EvalOptions::default()
+    };
+    return calculate_with_options(exp, env, &options);
+}
+
 /**
 计算函数。
 
+数字按 `N` 的语义计算，如 `BigDecimal` 的除法按选项中的精度舍入，`BigRational` 的除法是精确的，
+`i128` 溢出时返回 `CalcError::Overflow` 。
+
+* `exp` 输入的逆波兰式数组
+* `env` 计算环境，变量从这里取值
+* `options` 除法的精度、舍入方式以及整除和取余对负数的处理方式
+ */
+pub fn calculate_with_options<N: Numeric>(
+    exp: &[RPNItem],
+    env: &Env<N>,
+    options: &EvalOptions,
+) -> Result<N, CalcError> {
+    return run_rpn(exp, env, options, None);
+}
+
+/**
+计算函数，同时记录每一步的计算过程。
+
+出错时计算过程记录到出错的前一步为止。
+
 * `exp` 输入的逆波兰式数组
+* `env` 计算环境，变量从这里取值
+* `options` 除法的精度、舍入方式以及整除和取余对负数的处理方式
  */
-pub fn calculate(exp: &Vec<RPNItem>) -> Result<Num, String> {
+pub fn calculate_traced<N: Numeric>(
+    exp: &[RPNItem],
+    env: &Env<N>,
+    options: &EvalOptions,
+) -> (Result<N, CalcError>, Vec<EvalStep<N>>) {
+    let mut trace = vec![];
+    let result = run_rpn(exp, env, options, Some(&mut trace));
+    return (result, trace);
+}
+
+/// 依次执行逆波兰式中的每个元素，`trace` 不为 `None` 时记录每一步
+fn run_rpn<N: Numeric>(
+    exp: &[RPNItem],
+    env: &Env<N>,
+    options: &EvalOptions,
+    mut trace: Option<&mut Vec<EvalStep<N>>>,
+) -> Result<N, CalcError> {
     // 运算栈
-    let mut stack: VecDeque<Num> = VecDeque::new();
+    let mut stack: VecDeque<N> = VecDeque::new();
 
     // 遍历输入
     for item in exp {
-        // 检查 `item` 的类型
-        match item {
-            RPNItem::Operator(op) => {
-                // 如果是运算符，就根据运算符的类型取出运算栈中的数字进行操作
-                // 操作完成之后把结果 push_back 回运算栈
-                match op {
-                    parse::Operator::Add => {
-                        if let (Some(right), Some(left)) = (stack.pop_back(), stack.pop_back()) {
-                            let temp = left + right;
-                            stack.push_back(temp);
-                            continue;
-                        }
-                    }
-                    parse::Operator::Sub => {
-                        if let (Some(right), Some(left)) = (stack.pop_back(), stack.pop_back()) {
-                            let temp = left - right;
-                            stack.push_back(temp);
-                            continue;
-                        }
-                    }
-                    parse::Operator::Mul => {
-                        if let (Some(right), Some(left)) = (stack.pop_back(), stack.pop_back()) {
-                            let temp = left * right;
-                            stack.push_back(temp);
-                            continue;
-                        }
-                    }
-                    parse::Operator::Div => {
-                        if let (Some(right), Some(left)) = (stack.pop_back(), stack.pop_back()) {
-                            if right == BigDecimal::zero() {
-                                return Err("出现除 0 错误".to_owned());
-                            }
-                            let temp = left / right;
-                            stack.push_back(temp);
-                            continue;
-                        }
-                    }
-                    parse::Operator::Minus => {
-                        if let Some(temp) = stack.pop_back() {
-                            let temp: BigDecimal = -temp;
-                            stack.push_back(temp);
-                            continue;
-                        }
-                    }
-                };
-                // 如果运行到这里了，证明输入的逆波兰式有问题，应该排查上一步的语法分析。
+        // 记录计算过程时先保存运算前的栈
+        let before: Option<Vec<N>> = trace.as_ref().map(|_| stack.iter().cloned().collect());
 
-                return Err("出现未知错误！栈内数字数量不符".to_owned());
-            }
-            RPNItem::Number(n) => {
-                // 如果是数字类型，直接 push_back 进入运算栈。
-                stack.push_back(n.to_owned());
-            }
+        execute(item, &mut stack, env, options)?;
+
+        if let (Some(trace), Some(before)) = (trace.as_deref_mut(), before) {
+            // 每个元素执行完后都会向运算栈 push_back 一个数字
+            trace.push(EvalStep {
+                item: item.clone(),
+                before,
+                after: stack.iter().cloned().collect(),
+                value: stack.back().unwrap().clone(),
+            });
         }
     }
 
@@ -80,6 +448,394 @@ pub fn calculate(exp: &Vec<RPNItem>) -> Result<Num, String> {
     } else {
         // 如果运行到这里了，证明输入的逆波兰式有问题，应该排查上一步的语法分析。
 
-        return Err("出现未知错误，运算栈里面剩余的数字不对".to_owned());
+        return Err(CalcError::LeftoverOperands(stack.len()));
+    }
+}
+
+/// 执行逆波兰式中的一个元素，从运算栈中取出数字，把结果 push_back 回运算栈
+pub(crate) fn execute<N: Numeric>(
+    item: &RPNItem,
+    stack: &mut VecDeque<N>,
+    env: &Env<N>,
+    options: &EvalOptions,
+) -> Result<(), CalcError> {
+    // 检查 `item` 的类型
+    match item {
+        RPNItem::Operator {
+            op: parse::Operator::Minus,
+            span,
+        } => {
+            // 取负只需要一个数字
+            if let Some(temp) = stack.pop_back() {
+                stack.push_back(negate(&temp, *span)?);
+                return Ok(());
+            }
+            // 如果运行到这里了，证明输入的逆波兰式有问题，应该排查上一步的语法分析。
+
+            return Err(CalcError::StackUnderflow(*span));
+        }
+        RPNItem::Operator { op, span } => {
+            // 如果是双目运算符，就取出运算栈中的两个数字进行操作
+            // 操作完成之后把结果 push_back 回运算栈
+            if let (Some(right), Some(left)) = (stack.pop_back(), stack.pop_back()) {
+                stack.push_back(apply_binary(*op, &left, &right, *span, options)?);
+                return Ok(());
+            }
+            // 如果运行到这里了，证明输入的逆波兰式有问题，应该排查上一步的语法分析。
+
+            return Err(CalcError::StackUnderflow(*span));
+        }
+        RPNItem::Number { value, span } => {
+            // 如果是数字类型，转换为计算使用的数字类型后 push_back 进入运算栈。
+            stack.push_back(from_decimal(value, *span)?);
+        }
+        RPNItem::Variable { name, span } => {
+            // 如果是变量，就从计算环境中取出它的值 push_back 进入运算栈
+            match env.get(name) {
+                Some(n) => stack.push_back(n.to_owned()),
+                None => {
+                    return Err(CalcError::UnknownVariable {
+                        name: name.clone(),
+                        span: *span,
+                    });
+                }
+            }
+        }
+        RPNItem::Call { name, arity, span } => {
+            // 如果是函数调用，就在函数表里面找到这个函数
+            let function = find_function(name, *arity, *span)?;
+
+            if stack.len() < *arity {
+                // 如果运行到这里了，证明输入的逆波兰式有问题，应该排查上一步的语法分析。
+
+                return Err(CalcError::StackUnderflow(*span));
+            }
+
+            // 取出参数调用函数，把结果 push_back 回运算栈
+            let args: Vec<N> = stack.split_off(stack.len() - arity).into();
+            stack.push_back(call_function(name, function, &args, *span, options)?);
+        }
+    }
+    return Ok(());
+}
+
+/**
+双目运算，`op` 不能是取负。
+
+* `op` 运算符
+* `left` 左边的运算对象
+* `right` 右边的运算对象
+* `span` 运算符在输入串中的区间，出错时指出这个位置
+* `options` 除法的精度、舍入方式以及整除和取余对负数的处理方式
+ */
+pub(crate) fn apply_binary<N: Numeric>(
+    op: parse::Operator,
+    left: &N,
+    right: &N,
+    span: Span,
+    options: &EvalOptions,
+) -> Result<N, CalcError> {
+    let mode = options.division_mode;
+    let result = match op {
+        parse::Operator::Add => left.checked_add(right),
+        parse::Operator::Sub => left.checked_sub(right),
+        parse::Operator::Mul => left.checked_mul(right),
+        parse::Operator::Div => {
+            check_divisor(right, span)?;
+            left.checked_div(right, options)
+        }
+        parse::Operator::IntDiv => {
+            check_divisor(right, span)?;
+            left.checked_int_div(right, mode)
+        }
+        parse::Operator::Mod => {
+            check_divisor(right, span)?;
+            left.checked_rem(right, mode)
+        }
+        parse::Operator::Pow => return power(left, right, span, options),
+        parse::Operator::Minus => unreachable!("取负是单目运算符"),
+    };
+    return result.ok_or(CalcError::Overflow(span));
+}
+
+/// 取负，`span` 是取负运算符的区间
+pub(crate) fn negate<N: Numeric>(operand: &N, span: Span) -> Result<N, CalcError> {
+    return operand.checked_neg().ok_or(CalcError::Overflow(span));
+}
+
+/// 在函数表里面找到函数并检查参数个数，`span` 是函数调用的区间
+pub(crate) fn find_function(
+    name: &str,
+    arity: usize,
+    span: Span,
+) -> Result<&'static Function, CalcError> {
+    let function = match FUNCTION_LIST.get(name) {
+        Some(function) => function,
+        None => {
+            return Err(CalcError::UnknownFunction {
+                name: name.to_owned(),
+                span,
+            })
+        }
+    };
+
+    if !function.accepts(arity) {
+        return Err(CalcError::ArityMismatch {
+            name: name.to_owned(),
+            expected: function.arity_description(),
+            found: arity,
+            span,
+        });
+    }
+    return Ok(function);
+}
+
+/**
+调用函数。
+
+参数转换为十进制数后调用函数，再把结果转换回计算使用的数字类型。
+
+* `name` 函数名，用于错误信息
+* `function` 由 [`find_function`] 找到的函数
+* `args` 实参列表
+* `span` 函数调用在输入串中的区间
+* `options` 参数转换为十进制数时和函数结果的精度
+ */
+pub(crate) fn call_function<N: Numeric>(
+    name: &str,
+    function: &Function,
+    args: &[N],
+    span: Span,
+    options: &EvalOptions,
+) -> Result<N, CalcError> {
+    let args: Vec<Num> = args
+        .iter()
+        .map(|n| n.to_decimal(options.division_scale as i64, options.rounding))
+        .collect();
+    let result = (function.call)(&args, options).map_err(|reason| CalcError::DomainError {
+        function: name.to_owned(),
+        reason,
+        span,
+    })?;
+    return from_decimal(&result, span);
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        calculate, calculate_traced, calculate_with_mode, calculate_with_options, CalcError,
+        DivisionMode, Env,
+    };
+    use crate::lower::lower;
+    use crate::numeric::Numeric;
+    use crate::options::{EvalOptions, FractionStyle};
+    use crate::parse::parse;
+    use crate::parse::RPNItem;
+    use crate::token::tokenization;
+    use crate::token_render::*;
+    use bigdecimal::BigDecimal;
+    use num_rational::BigRational;
+
+    #[test]
+    fn calculate_test_fail() {
+        // 测试计算错误的类型和位置
+        let input_vec = vec![
+            ("1/0", Some(1..2)),
+            ("3 + 4 % (2 - 2)", Some(6..7)),
+            ("7 // 0", Some(2..4)),
+            ("0 ^ (-1)", Some(2..3)),
+            ("2 ** 0.5", Some(2..4)),
+            ("x + 1", Some(0..1)),
+            ("sqrt(-1)", Some(0..8)),
+            ("1 + foo(1)", Some(4..10)),
+            ("max()", Some(0..5)),
+        ];
+        for (input, span) in input_vec {
+            let tokens = tokenization(input).unwrap();
+            let expr = parse(&mut TokenRender::new_with_tokens(tokens)).unwrap();
+            let err = calculate(&lower(&expr)).unwrap_err();
+            assert_eq!(err.span().map(|s| s.start..s.end), span, "input: {}", input);
+        }
+
+        // 测试可以按错误的原因匹配
+        let tokens = tokenization("1+2/(3-3)").unwrap();
+        let expr = parse(&mut TokenRender::new_with_tokens(tokens)).unwrap();
+        assert!(matches!(
+            calculate(&lower(&expr)),
+            Err(CalcError::DivisionByZero(_))
+        ));
+    }
+
+    #[test]
+    fn power_test() {
+        // 测试整数次乘方的结果是精确的，乘方是右结合的
+        let input_vec = vec![
+            ("2^10", "1024"),
+            ("(-3)^3", "-27"),
+            ("1.5^2", "2.25"),
+            ("0.1^3", "0.001"),
+            ("10^20", "100000000000000000000"),
+            ("7^0", "1"),
+            ("2^(0-2)", "0.25"),
+            ("2^3^2", "512"),
+            ("2**3**2", "512"),
+            ("1^100000", "1"),
+            ("1^(0-100000)", "1"),
+        ];
+        for (input, expected) in input_vec {
+            let tokens = tokenization(input).unwrap();
+            let rpn = lower(&parse(&mut TokenRender::new_with_tokens(tokens)).unwrap());
+            let expected: BigDecimal = expected.parse().unwrap();
+            assert_eq!(calculate(&rpn), Ok(expected), "input: {}", input);
+        }
+
+        // 测试指数不是整数或者绝对值超过 MAX_EXPONENT 时指出乘方运算符
+        let input_vec = vec![
+            ("2^0.5", 1..2),
+            ("4^(1/2)", 1..2),
+            ("1^100001", 1..2),
+            ("1^(0-100001)", 1..2),
+        ];
+        for (input, span) in input_vec {
+            let tokens = tokenization(input).unwrap();
+            let rpn = lower(&parse(&mut TokenRender::new_with_tokens(tokens)).unwrap());
+            match calculate(&rpn) {
+                Err(CalcError::InvalidExponent { span: s, .. }) => {
+                    assert_eq!(s.start..s.end, span, "input: {}", input)
+                }
+                other => panic!("input: {}, result: {:?}", input, other),
+            }
+        }
+    }
+
+    #[test]
+    fn division_mode_test() {
+        // 测试整除和取余对负数的处理方式，依次为 Floored Truncated
+        let input_vec = vec![
+            ("-7//2", ["-4", "-3"]),
+            ("-7%2", ["1", "-1"]),
+            ("7%(-2)", ["-1", "1"]),
+            ("7.5%2", ["1.5", "1.5"]),
+            ("-7.5//2", ["-4", "-3"]),
+        ];
+        for (input, expected) in input_vec {
+            let tokens = tokenization(input).unwrap();
+            let rpn = lower(&parse(&mut TokenRender::new_with_tokens(tokens)).unwrap());
+            let actual = DivisionMode::ALL.map(|mode| {
+                calculate_with_mode(&rpn, &Env::new(), mode)
+                    .unwrap()
+                    .to_string()
+            });
+            assert_eq!(actual, expected, "input: {}", input);
+        }
+
+        // 两种方式下除数为 0 都指出运算符
+        let input_vec = vec![("7 // 0", 2..4), ("-7 % (1 - 1)", 3..4)];
+        for (input, span) in input_vec {
+            let tokens = tokenization(input).unwrap();
+            let rpn = lower(&parse(&mut TokenRender::new_with_tokens(tokens)).unwrap());
+            for mode in DivisionMode::ALL {
+                match calculate_with_mode(&rpn, &Env::new(), mode) {
+                    Err(CalcError::DivisionByZero(s)) => {
+                        assert_eq!(s.start..s.end, span, "input: {}", input)
+                    }
+                    other => panic!("input: {}, mode: {:?}, result: {:?}", input, mode, other),
+                }
+            }
+        }
+
+        // 命令行和交互式命令行按名字选择整除方式
+        for mode in DivisionMode::ALL {
+            assert_eq!(mode.to_string().parse(), Ok(mode));
+        }
+        assert!("round".parse::<DivisionMode>().is_err());
+    }
+
+    #[test]
+    fn calculate_backend_test() {
+        // 测试同一个逆波兰式按不同的数字类型计算，依次为 BigDecimal BigRational f64 i128
+        let input_vec = vec![
+            ("7 / 2 * 2", ["7", "7", "7", "6"]),
+            ("0.1 + 0.2", ["0.3", "0.3", "0.3", "0.1 不能用 i128 表示"]),
+            (
+                "2 ^ 127",
+                [
+                    "170141183460469231731687303715884105728",
+                    "170141183460469231731687303715884105728",
+                    "170141183460469230000000000000000000000",
+                    "运算结果超出了数字类型的范围",
+                ],
+            ),
+            ("-7 // 2 + max(1, 2)", ["-2", "-2", "-2", "-2"]),
+        ];
+        let options = EvalOptions {
+            fraction: FractionStyle::Off,
+            ..EvalOptions::default()
+        };
+        for (input, expected) in input_vec {
+            let tokens = tokenization(input).unwrap();
+            let rpn = lower(&parse(&mut TokenRender::new_with_tokens(tokens)).unwrap());
+            let actual = [
+                format_result::<BigDecimal>(&rpn, &options),
+                format_result::<BigRational>(&rpn, &options),
+                format_result::<f64>(&rpn, &options),
+                format_result::<i128>(&rpn, &options),
+            ];
+            assert_eq!(actual, expected, "input: {}", input);
+        }
+    }
+
+    /// 按数字类型 `N` 计算并格式化结果，出错时返回错误信息
+    fn format_result<N: Numeric>(rpn: &[RPNItem], options: &EvalOptions) -> String {
+        return match calculate_with_options(rpn, &Env::<N>::default(), options) {
+            Ok(n) => n.format(options),
+            Err(e) => e.to_string(),
+        };
+    }
+
+    #[test]
+    fn calculate_traced_test() {
+        // 测试记录的每一步：执行的元素、执行前后的运算栈
+        let tokens = tokenization("1 + 2 * (-3)").unwrap();
+        let rpn = lower(&parse(&mut TokenRender::new_with_tokens(tokens)).unwrap());
+        let (result, trace) = calculate_traced(&rpn, &Env::new(), &EvalOptions::default());
+        assert_eq!(result.unwrap().to_string(), "-5");
+
+        let expected = vec![
+            "1: [ ] -> [ 1 ]",
+            "2: [ 1 ] -> [ 1 2 ]",
+            "3: [ 1 2 ] -> [ 1 2 3 ]",
+            "@: [ 1 2 3 ] -> [ 1 2 -3 ]",
+            "*: [ 1 2 -3 ] -> [ 1 -6 ]",
+            "+: [ 1 -6 ] -> [ -5 ]",
+        ];
+        let join = |stack: &[BigDecimal]| {
+            let mut s = String::from("[ ");
+            for n in stack {
+                s.push_str(&format!("{} ", n));
+            }
+            s.push(']');
+            s
+        };
+        let actual = trace
+            .iter()
+            .map(|step| {
+                format!(
+                    "{}: {} -> {}",
+                    step.item,
+                    join(&step.before),
+                    join(&step.after)
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(actual, expected);
+        assert_eq!(trace[3].value.to_string(), "-3");
+
+        // 出错时记录到出错的前一步为止
+        let tokens = tokenization("2 * (1 / 0)").unwrap();
+        let rpn = lower(&parse(&mut TokenRender::new_with_tokens(tokens)).unwrap());
+        let (result, trace) = calculate_traced(&rpn, &Env::new(), &EvalOptions::default());
+        assert!(matches!(result, Err(CalcError::DivisionByZero(_))));
+        assert_eq!(trace.len(), 3);
     }
 }
@@ -0,0 +1,386 @@
+use bigdecimal::BigDecimal;
+use std::io::{self, BufRead};
+use toy_parse::dot::{parse_tree_to_dot, program_to_dot};
+use toy_parse::parse::{parse_program, parse_program_recovering};
+use toy_parse::token::{tokenization, tokenization_recovering};
+use toy_parse::token_render::TokenRender;
+use toy_parse::{
+    evaluate_rpn, evaluate_with_options, Backend, BigRational, Diagnostics, Env, EvalOptions,
+    Notation, Numeric, MAX_SCALE,
+};
+
+/// 交互式命令行的模块
+mod repl;
+
+/// 命令行的用法说明
+const USAGE: &str = "用法:
+    toy-parse-cli [选项] <表达式>...  依次计算每个参数，变量在参数之间保留
+    toy-parse-cli [选项] -            从标准输入逐行读取并计算，变量在行之间保留
+    toy-parse-cli [选项] --repl       启动交互式命令行，输入 :help 查看可用的命令
+    toy-parse-cli -h | --help         显示本说明
+
+选项:
+    --division-scale <位数>    除法和函数的结果保留的小数位数，默认 64 ，最多 1000
+    --output-scale <位数>      输出的数字保留的小数位数，默认 15 ，最多 1000
+    --rounding <方式>          除法和输出时的舍入方式，默认 truncate ，
+                               可用的有 half-even 、half-up 、floor 、ceiling 、truncate
+    --division-mode <方式>     整除 // 和取余 % 对负数的处理方式，默认 floored 商向负无穷取整，
+                               truncated 商向 0 取整，如 -7 // 2 分别为 -4 和 -3
+    --backend <类型>           计算使用的数字类型，默认 decimal ，可用的有 decimal 十进制数 、
+                               rational 有理数 、float 浮点数 、integer 整数
+    --notation <形式>          打印的表达式形式，默认 postfix 逆波兰式，可用的有 postfix 、
+                               prefix 波兰式 、infix 完全加括号的中缀式，取负都写作 @ ；
+                               还可以是 quadruple 四元式 、triple 三元式，以表格打印中间代码；
+                               dag 打印消除公共子表达式后的逆波兰式、四元式和少算的次数
+    --exact                    使用精确的有理数运算，等价于 --backend rational
+    --trace                    在逆波兰式后面打印每一步的计算过程
+    --parse-trace              在计算前打印语法分析的最左推导和调用过程
+    --rpn                      把输入当作逆波兰式直接计算，如 '3 4 + 2 *' ，
+                               元素之间用空白分隔，取负写作 @ ，函数调用写作 max:2
+    --optimize                 计算前折叠常量并去掉乘以 1 、加减 0 和两次取负，打印每一次化简
+    --dot <图>                 不计算，只打印 Graphviz DOT 格式的图，可用的有 ast 抽象语法树 、
+                               parse 语法分析树，如 toy-parse-cli --dot ast '3*4*5/(-2)' | dot -Tpng
+    --fraction <形式>          精确运算时结果附带的分数形式，默认 improper ，
+                               可用的有 improper 、mixed 、off
+
+以 - 开头的参数视为带负号的表达式，如 toy-parse-cli -2+3 ；以 -- 开头的参数视为选项。
+
+计算成功时向标准输出打印逆波兰式（或者 --notation 选择的形式）和计算结果，退出码为 0 ；
+出错时向标准错误打印错误信息并停止计算，退出码为 1 ；用法错误时退出码为 2 。";
+
+/**
+命令行的选项。
+
+* `backend` 计算使用的数字类型
+* `notation` 打印的表达式形式
+* `parse_trace` 是否打印语法分析的过程
+* `rpn` 是否把输入当作逆波兰式直接计算
+* `dot` 只打印哪种 Graphviz DOT 格式的图，为 `None` 时正常计算
+* `eval` 计算和输出的选项
+
+ */
+#[derive(Clone, Copy)]
+struct CliOptions {
+    /// 计算使用的数字类型
+    backend: Backend,
+    /// 打印的表达式形式
+    notation: Notation,
+    /// 是否打印语法分析的过程
+    parse_trace: bool,
+    /// 是否把输入当作逆波兰式直接计算
+    rpn: bool,
+    /// 只打印哪种 Graphviz DOT 格式的图，为 `None` 时正常计算
+    dot: Option<DotKind>,
+    /// 计算和输出的选项
+    eval: EvalOptions,
+}
+
+/**
+命令行可以打印的 Graphviz DOT 格式的图。
+
+* `DotKind::Ast` 抽象语法树
+* `DotKind::Parse` 语法分析树，非终结符为文法中的名字
+
+ */
+#[derive(Clone, Copy)]
+enum DotKind {
+    /// 抽象语法树
+    Ast,
+    /// 语法分析树
+    Parse,
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    std::process::exit(run(&args));
+}
+
+/**
+运行命令行界面，返回进程的退出码。
+
+* `args` 除程序名以外的命令行参数
+ */
+fn run(args: &[String]) -> i32 {
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", USAGE);
+        return 0;
+    }
+
+    let (options, inputs) = match parse_options(args) {
+        Ok(result) => result,
+        Err(reason) => {
+            eprintln!("{}\n\n{}", reason, USAGE);
+            return 2;
+        }
+    };
+
+    if inputs.is_empty() {
+        eprintln!("没有需要计算的表达式\n\n{}", USAGE);
+        return 2;
+    }
+
+    return match options.backend {
+        Backend::Decimal => run_with::<BigDecimal>(options, inputs),
+        Backend::Rational => run_with::<BigRational>(options, inputs),
+        Backend::Float => run_with::<f64>(options, inputs),
+        Backend::Integer => run_with::<i128>(options, inputs),
+    };
+}
+
+/// 使用数字类型 `N` 计算所有输入，返回进程的退出码
+fn run_with<N: Numeric>(options: CliOptions, inputs: &[String]) -> i32 {
+    if inputs.len() == 1 && inputs[0] == "--repl" {
+        return repl::run::<N>(options.eval, options.notation);
+    }
+
+    // 所有输入共用一个计算环境
+    let mut env = Env::<N>::default();
+
+    if inputs.len() == 1 && inputs[0] == "-" {
+        // 从标准输入逐行读取
+        for line in io::stdin().lock().lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => {
+                    eprintln!("读取标准输入失败: {}", e);
+                    return 1;
+                }
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            if !run_input(&line, &mut env, options) {
+                return 1;
+            }
+        }
+        return 0;
+    }
+
+    if let Some(arg) = inputs.iter().find(|arg| arg.starts_with("--")) {
+        // 以 - 开头的可能是带负号的表达式，只有以 -- 开头的才是选项
+        eprintln!("未知的选项: {}\n\n{}", arg, USAGE);
+        return 2;
+    }
+
+    for input in inputs {
+        if !run_input(input, &mut env, options) {
+            return 1;
+        }
+    }
+    return 0;
+}
+
+/// 取出开头的选项，返回选项和剩下的参数
+fn parse_options(args: &[String]) -> Result<(CliOptions, &[String]), String> {
+    let mut backend = Backend::Decimal;
+    let mut notation = Notation::Postfix;
+    let mut parse_trace = false;
+    let mut rpn = false;
+    let mut dot = None;
+    let mut options = EvalOptions::default();
+    let mut rest = args;
+
+    while let [name, tail @ ..] = rest {
+        // 不带参数的选项
+        if name == "--exact" {
+            backend = Backend::Rational;
+            rest = tail;
+            continue;
+        }
+        if name == "--trace" {
+            options.trace = true;
+            rest = tail;
+            continue;
+        }
+        if name == "--parse-trace" {
+            parse_trace = true;
+            rest = tail;
+            continue;
+        }
+        if name == "--rpn" {
+            rpn = true;
+            rest = tail;
+            continue;
+        }
+        if name == "--optimize" {
+            options.optimize = true;
+            rest = tail;
+            continue;
+        }
+        if !matches!(
+            name.as_str(),
+            "--division-scale"
+                | "--output-scale"
+                | "--rounding"
+                | "--division-mode"
+                | "--fraction"
+                | "--backend"
+                | "--notation"
+                | "--dot"
+        ) {
+            break;
+        }
+        let value = match tail.first() {
+            Some(value) => value,
+            None => return Err(format!("选项 {} 缺少参数", name)),
+        };
+
+        match name.as_str() {
+            "--division-scale" => options.division_scale = parse_scale(name, value)?,
+            "--output-scale" => options.output_scale = parse_scale(name, value)?,
+            "--rounding" => options.rounding = value.parse()?,
+            "--division-mode" => options.division_mode = value.parse()?,
+            "--backend" => backend = value.parse()?,
+            "--notation" => notation = value.parse()?,
+            "--dot" => {
+                dot = match value.as_str() {
+                    "ast" => Some(DotKind::Ast),
+                    "parse" => Some(DotKind::Parse),
+                    _ => {
+                        return Err(format!(
+                            "--dot 的图只能是 ast 或者 parse ，却得到了 {}",
+                            value
+                        ))
+                    }
+                }
+            }
+            _ => options.fraction = value.parse()?,
+        }
+        rest = &tail[1..];
+    }
+
+    let options = CliOptions {
+        backend,
+        notation,
+        parse_trace,
+        rpn,
+        dot,
+        eval: options,
+    };
+    return Ok((options, rest));
+}
+
+/// 解析小数位数，位数必须是不超过 `MAX_SCALE` 的非负整数
+fn parse_scale(name: &str, value: &str) -> Result<u64, String> {
+    let scale = value
+        .parse::<u64>()
+        .map_err(|_| format!("{} 的位数必须是非负整数，却得到了 {}", name, value))?;
+    if scale > MAX_SCALE {
+        return Err(format!(
+            "{} 的位数不能超过 {}，却得到了 {}",
+            name, MAX_SCALE, value
+        ));
+    }
+    return Ok(scale);
+}
+
+/// 计算一段输入并打印结果，返回是否成功
+fn run_input<N: Numeric>(input: &str, env: &mut Env<N>, options: CliOptions) -> bool {
+    if !input.is_ascii() {
+        // 和图形界面一样只接受 ASCII 输入
+        eprintln!("输入中包含非 ASCII 字符: {}", input);
+        return false;
+    }
+
+    if options.rpn {
+        return run_rpn_input(input, env, options);
+    }
+
+    if let Some(kind) = options.dot {
+        return print_dot(input, kind);
+    }
+
+    if options.parse_trace {
+        print_parse_trace(input);
+    }
+
+    match evaluate_with_options(input, env, options.eval) {
+        Ok(evaluation) => {
+            if options.eval.optimize {
+                println!("{}", evaluation.rewrite_text());
+            }
+            println!("{}", evaluation.notation_text(options.notation));
+            if options.eval.trace {
+                println!("{}", evaluation.trace_text());
+            }
+
+            // 出错时停止执行，最后一行就是出错的语句
+            let output = evaluation.output_text();
+            let mut lines: Vec<&str> = output.lines().collect();
+            let failed_line = if evaluation.is_success() {
+                None
+            } else {
+                lines.pop()
+            };
+            for line in lines {
+                println!("{}", line);
+            }
+            if let Some(line) = failed_line {
+                eprintln!("{}", line);
+            }
+
+            return evaluation.is_success();
+        }
+        Err(report) => {
+            eprint!("{}", report);
+            return false;
+        }
+    }
+}
+
+/// 直接计算一段逆波兰式并打印结果，返回是否成功
+fn run_rpn_input<N: Numeric>(input: &str, env: &Env<N>, options: CliOptions) -> bool {
+    match evaluate_rpn(input, env, &options.eval) {
+        Ok(value) => {
+            println!("计算结果为: {} 。", value.format(&options.eval));
+            return true;
+        }
+        Err(report) => {
+            eprint!("{}", report);
+            return false;
+        }
+    }
+}
+
+/// 打印语法分析的最左推导和调用过程，词法分析出错时不打印，错误留给之后的计算报告
+fn print_parse_trace(input: &str) {
+    let tokens = match tokenization(input) {
+        Ok(tokens) => tokens,
+        Err(_) => return,
+    };
+    let mut render = TokenRender::new_with_tokens(tokens);
+    render.enable_trace();
+    // 出错时跟踪记录到出错的位置为止，错误由之后的计算报告
+    let _ = parse_program(&mut render);
+
+    let trace = render.take_trace().unwrap();
+    println!("最左推导:\n{}\n", trace.derivation_text());
+    println!("调用过程:\n{}", trace.call_trace_text());
+}
+
+/// 打印一段输入的 Graphviz DOT 格式的图，返回是否成功，出错时打印错误报告
+fn print_dot(input: &str, kind: DotKind) -> bool {
+    // 和计算时一样收集所有词法错误
+    let (tokens, errors) = tokenization_recovering(input);
+    if !errors.is_empty() {
+        eprint!("{}", Diagnostics::from_lexer_errors(errors, input));
+        return false;
+    }
+    let mut render = TokenRender::new_with_tokens(tokens);
+    if let DotKind::Parse = kind {
+        render.enable_trace();
+    }
+    let program = match parse_program_recovering(&mut render) {
+        Ok(program) => program,
+        Err(errors) => {
+            eprint!("{}", Diagnostics::from_parse_errors(errors, input));
+            return false;
+        }
+    };
+
+    match kind {
+        DotKind::Ast => println!("{}", program_to_dot(&program, input)),
+        DotKind::Parse => println!("{}", parse_tree_to_dot(&render.take_trace().unwrap())),
+    }
+    return true;
+}
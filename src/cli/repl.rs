@@ -0,0 +1,238 @@
+use crate::parse_scale;
+use rustyline::{error::ReadlineError, Editor};
+use std::path::PathBuf;
+use toy_parse::{evaluate_rpn, evaluate_with_options, Env, EvalOptions, Notation, Numeric};
+
+/// 交互式命令行的提示符
+const PROMPT: &str = ">> ";
+
+/// 保存上一次计算结果的变量名
+const ANS: &str = "ans";
+
+/// 交互式命令行的帮助信息
+const HELP: &str = "输入表达式或者语句后回车计算，上一次的计算结果保存在变量 ans 中。
+
+可用的命令:
+    :rpn on | off               是否显示表达式，默认显示
+    :notation <形式>            设置显示的表达式形式，默认 postfix 逆波兰式，
+                                可用的有 postfix 、prefix 波兰式 、infix 完全加括号的中缀式 、
+                                quadruple 四元式 、triple 三元式 、dag 消除公共子表达式
+    :trace on | off             是否显示每一步的计算过程，默认不显示
+    :optimize on | off          是否在计算前化简表达式并显示每一次化简，默认不化简
+    :precision <位数>           设置输出的数字保留的小数位数，默认 15 位，最多 1000 位
+    :division-scale <位数>      设置除法和函数的结果保留的小数位数，默认 64 位，最多 1000 位
+    :rounding <方式>            设置除法和输出时的舍入方式，默认 truncate ，
+                                可用的有 half-even 、half-up 、floor 、ceiling 、truncate
+    :division-mode <方式>       设置整除和取余对负数的处理方式，默认 floored 商向负无穷取整，
+                                truncated 商向 0 取整
+    :fraction <形式>            设置精确运算时附带的分数形式，默认 improper ，
+                                可用的有 improper 、mixed 、off
+    :eval-rpn <逆波兰式>        直接计算逆波兰式，如 :eval-rpn 3 4 + 2 * ，取负写作 @
+    :vars                       列出所有已经定义的变量
+    :help                       显示本说明
+    :quit                       退出，也可以使用 Ctrl-D";
+
+/**
+交互式命令行的设置。
+
+* `show_rpn` 是否显示表达式
+* `notation` 显示的表达式形式
+* `options` 计算和输出的选项
+
+ */
+struct Settings {
+    /// 是否显示表达式
+    show_rpn: bool,
+    /// 显示的表达式形式
+    notation: Notation,
+    /// 计算和输出的选项
+    options: EvalOptions,
+}
+
+/**
+以 `:` 开头的命令的执行结果。
+
+* `Command::Continue` 继续读取下一行
+* `Command::Quit` 退出交互式命令行
+
+ */
+enum Command {
+    /// 继续读取下一行
+    Continue,
+    /// 退出交互式命令行
+    Quit,
+}
+
+/**
+运行交互式命令行，返回进程的退出码。
+
+支持行编辑和历史记录，历史记录保存在用户目录下的 `.toy_parse_history` 中。
+所有输入共用一个计算环境，变量在输入之间保留，计算使用的数字类型为 `N` 。
+
+* `options` 初始的计算和输出选项，可以用命令修改
+* `notation` 初始的表达式形式，可以用命令修改
+ */
+pub fn run<N: Numeric>(options: EvalOptions, notation: Notation) -> i32 {
+    let mut editor = Editor::<()>::new();
+    let history = history_path();
+    if let Some(path) = &history {
+        // 第一次运行时还没有历史记录文件，忽略错误
+        let _ = editor.load_history(path);
+    }
+
+    println!("输入 :help 查看可用的命令，:quit 退出。");
+
+    let mut env = Env::<N>::default();
+    let mut settings = Settings {
+        show_rpn: true,
+        notation,
+        options,
+    };
+
+    let code = loop {
+        let line = match editor.readline(PROMPT) {
+            Ok(line) => line,
+            // Ctrl-C 放弃当前行
+            Err(ReadlineError::Interrupted) => continue,
+            // Ctrl-D 退出
+            Err(ReadlineError::Eof) => break 0,
+            Err(e) => {
+                eprintln!("读取输入失败: {}", e);
+                break 1;
+            }
+        };
+
+        let input = line.trim();
+        if input.is_empty() {
+            continue;
+        }
+        editor.add_history_entry(input);
+
+        if let Some(command) = input.strip_prefix(':') {
+            match run_command(command, &mut env, &mut settings) {
+                Command::Continue => continue,
+                Command::Quit => break 0,
+            }
+        }
+
+        run_input(input, &mut env, &settings);
+    };
+
+    if let Some(path) = &history {
+        if let Err(e) = editor.save_history(path) {
+            eprintln!("保存历史记录失败: {}", e);
+        }
+    }
+    return code;
+}
+
+/// 历史记录文件的路径，找不到用户目录时不保存历史记录
+fn history_path() -> Option<PathBuf> {
+    let home = std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))?;
+    return Some(PathBuf::from(home).join(".toy_parse_history"));
+}
+
+/// 执行以 `:` 开头的命令，`command` 不包含开头的 `:`
+fn run_command<N: Numeric>(command: &str, env: &mut Env<N>, settings: &mut Settings) -> Command {
+    let mut words = command.split_whitespace();
+    let name = words.next().unwrap_or("");
+    let arg = words.next();
+    // 命令名后面的全部内容，用于参数中带空白的命令
+    let rest = command.trim_start()[name.len()..].trim();
+
+    match (name, arg) {
+        ("rpn", Some("on")) => settings.show_rpn = true,
+        ("rpn", Some("off")) => settings.show_rpn = false,
+        ("notation", Some(notation)) => match notation.parse() {
+            Ok(notation) => settings.notation = notation,
+            Err(reason) => eprintln!("{}", reason),
+        },
+        ("trace", Some("on")) => settings.options.trace = true,
+        ("trace", Some("off")) => settings.options.trace = false,
+        ("optimize", Some("on")) => settings.options.optimize = true,
+        ("optimize", Some("off")) => settings.options.optimize = false,
+        ("precision", Some(n)) => match parse_scale(":precision", n) {
+            Ok(n) => settings.options.output_scale = n,
+            Err(reason) => eprintln!("{}", reason),
+        },
+        ("division-scale", Some(n)) => match parse_scale(":division-scale", n) {
+            Ok(n) => settings.options.division_scale = n,
+            Err(reason) => eprintln!("{}", reason),
+        },
+        ("rounding", Some(mode)) => match mode.parse() {
+            Ok(mode) => settings.options.rounding = mode,
+            Err(reason) => eprintln!("{}", reason),
+        },
+        ("division-mode", Some(mode)) => match mode.parse() {
+            Ok(mode) => settings.options.division_mode = mode,
+            Err(reason) => eprintln!("{}", reason),
+        },
+        ("fraction", Some(style)) => match style.parse() {
+            Ok(style) => settings.options.fraction = style,
+            Err(reason) => eprintln!("{}", reason),
+        },
+        ("eval-rpn", _) => run_rpn_input(rest, env, settings),
+        ("vars", None) => {
+            let mut empty = true;
+            for (name, value) in env.iter() {
+                println!("{} = {}", name, value.format(&settings.options));
+                empty = false;
+            }
+            if empty {
+                println!("还没有定义任何变量");
+            }
+        }
+        ("help", None) => println!("{}", HELP),
+        ("quit", None) | ("q", None) => return Command::Quit,
+        _ => eprintln!("未知的命令: :{}，输入 :help 查看可用的命令", command),
+    }
+    return Command::Continue;
+}
+
+/// 计算一行输入并打印结果，计算成功时把最后一条语句的值保存到 `ans`
+fn run_input<N: Numeric>(input: &str, env: &mut Env<N>, settings: &Settings) {
+    if !input.is_ascii() {
+        // 和图形界面一样只接受 ASCII 输入
+        eprintln!("输入中包含非 ASCII 字符");
+        return;
+    }
+
+    match evaluate_with_options(input, env, settings.options) {
+        Ok(evaluation) => {
+            if settings.options.optimize {
+                println!("{}", evaluation.rewrite_text());
+            }
+            if settings.show_rpn {
+                println!("{}", evaluation.notation_text(settings.notation));
+            }
+            if settings.options.trace {
+                println!("{}", evaluation.trace_text());
+            }
+            println!("{}", evaluation.output_text());
+
+            if let Some(value) = evaluation.value() {
+                env.set(ANS, value.clone());
+            }
+        }
+        Err(report) => {
+            // 和图形界面一样输出 ariadne 的错误报告
+            eprint!("{}", report);
+        }
+    }
+}
+
+/// 直接计算一段逆波兰式并打印结果，计算成功时把结果保存到 `ans`
+fn run_rpn_input<N: Numeric>(input: &str, env: &mut Env<N>, settings: &Settings) {
+    if !input.is_ascii() {
+        eprintln!("输入中包含非 ASCII 字符");
+        return;
+    }
+
+    match evaluate_rpn(input, env, &settings.options) {
+        Ok(value) => {
+            println!("计算结果为: {} 。", value.format(&settings.options));
+            env.set(ANS, value);
+        }
+        Err(report) => eprint!("{}", report),
+    }
+}
@@ -0,0 +1,560 @@
+use crate::ast::{Expr, ExprKind};
+use crate::calculate::{execute, CalcError, Env};
+use crate::lower::lower;
+use crate::numeric::Numeric;
+use crate::options::EvalOptions;
+use crate::parse::{Operator, RPNItem};
+use crate::quadruple::{Operand, QuadOp, Quadruple};
+use crate::token::Span;
+use bigdecimal::BigDecimal;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+type Num = BigDecimal;
+
+/**
+DAG 节点的具体内容，子节点用编号表示。
+
+* `DagKind::Number` 数字
+* `DagKind::Variable` 变量
+* `DagKind::Unary` 单目运算
+* `DagKind::Binary` 双目运算
+* `DagKind::Call` 函数调用
+
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum DagKind {
+    /// 数字
+    Number(Num),
+    /// 变量
+    Variable(String),
+    /// 单目运算
+    Unary {
+        /// 运算符
+        op: Operator,
+        /// 操作数的编号
+        operand: usize,
+    },
+    /// 双目运算
+    Binary {
+        /// 运算符
+        op: Operator,
+        /// 左操作数的编号
+        left: usize,
+        /// 右操作数的编号
+        right: usize,
+    },
+    /// 函数调用
+    Call {
+        /// 函数名
+        name: String,
+        /// 参数的编号
+        args: Vec<usize>,
+    },
+}
+
+/**
+DAG 的节点，相同的子表达式只有一个节点。
+
+* `kind` 节点的具体内容
+* `span` 第一次出现时在输入串中的区间，运算为运算符的区间，函数调用为整个调用的区间
+* `uses` 被其他节点引用的次数，大于 1 时是公共子表达式
+
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct DagNode {
+    /// 节点的具体内容
+    pub kind: DagKind,
+    /// 第一次出现时在输入串中的区间
+    pub span: Span,
+    /// 被其他节点引用的次数
+    pub uses: usize,
+}
+
+/**
+逆波兰式中消除公共子表达式后的元素。
+
+* `DagItem::Item` 和普通的逆波兰式相同的元素
+* `DagItem::Dup` 复制栈顶的数字
+* `DagItem::Store` 把栈顶的数字保存到临时变量，不出栈
+* `DagItem::Load` 把临时变量的值放入运算栈
+
+ */
+#[derive(Debug, Clone)]
+pub enum DagItem {
+    /// 和普通的逆波兰式相同的元素
+    Item(RPNItem),
+    /// 复制栈顶的数字
+    Dup,
+    /// 把栈顶的数字保存到临时变量，编号从 1 开始
+    Store(usize),
+    /// 把临时变量的值放入运算栈
+    Load(usize),
+}
+
+impl fmt::Display for DagItem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DagItem::Item(item) => write!(f, "{}", item),
+            DagItem::Dup => write!(f, "dup"),
+            DagItem::Store(temp) => write!(f, "store:t{}", temp),
+            DagItem::Load(temp) => write!(f, "load:t{}", temp),
+        }
+    }
+}
+
+/**
+表达式消除公共子表达式后得到的有向无环图。
+
+子节点总是排在父节点前面，最后一个节点就是根节点。
+ */
+#[derive(Debug, Clone)]
+pub struct Dag {
+    nodes: Vec<DagNode>,
+    /// 普通的逆波兰式中运算符和函数调用的个数
+    naive_operations: usize,
+}
+
+impl Dag {
+    /** 把表达式中相同的子表达式合并为同一个节点。 */
+    pub fn new(expr: &Expr) -> Dag {
+        let mut dag = Dag {
+            nodes: vec![],
+            naive_operations: lower(expr).iter().filter(|item| is_operation(item)).count(),
+        };
+        let mut index = HashMap::new();
+        dag.insert(expr, &mut index);
+        return dag;
+    }
+
+    /** 所有节点，子节点总是排在父节点前面。 */
+    pub fn nodes(&self) -> &[DagNode] {
+        return &self.nodes;
+    }
+
+    /** 根节点的编号。 */
+    pub fn root(&self) -> usize {
+        return self.nodes.len() - 1;
+    }
+
+    /** 消除公共子表达式后需要做的运算次数，也就是运算和函数调用节点的个数。 */
+    pub fn operation_count(&self) -> usize {
+        return self
+            .nodes
+            .iter()
+            .filter(|node| !is_leaf(&node.kind))
+            .count();
+    }
+
+    /** 普通的逆波兰式需要做的运算次数。 */
+    pub fn naive_operation_count(&self) -> usize {
+        return self.naive_operations;
+    }
+
+    /** 被引用不止一次的运算和函数调用节点，也就是公共子表达式的个数。 */
+    pub fn shared_count(&self) -> usize {
+        return self
+            .nodes
+            .iter()
+            .filter(|node| !is_leaf(&node.kind) && node.uses > 1)
+            .count();
+    }
+
+    /// 插入表达式对应的节点，已经有相同的节点时直接返回它的编号
+    fn insert(&mut self, expr: &Expr, index: &mut HashMap<String, usize>) -> usize {
+        let (kind, span) = match &expr.kind {
+            ExprKind::Number(n) => (DagKind::Number(n.clone()), expr.span),
+            ExprKind::Variable(name) => (DagKind::Variable(name.clone()), expr.span),
+            ExprKind::Unary {
+                op,
+                op_span,
+                operand,
+            } => {
+                let operand = self.insert(operand, index);
+                (DagKind::Unary { op: *op, operand }, *op_span)
+            }
+            ExprKind::Binary {
+                op,
+                op_span,
+                left,
+                right,
+            } => {
+                let left = self.insert(left, index);
+                let right = self.insert(right, index);
+                (
+                    DagKind::Binary {
+                        op: *op,
+                        left,
+                        right,
+                    },
+                    *op_span,
+                )
+            }
+            ExprKind::Call { name, args, .. } => {
+                let args = args.iter().map(|arg| self.insert(arg, index)).collect();
+                let name = name.clone();
+                (DagKind::Call { name, args }, expr.span)
+            }
+        };
+
+        // 子节点被引用一次
+        for child in children(&kind) {
+            self.nodes[child].uses += 1;
+        }
+
+        let key = node_key(&kind);
+        if let Some(&id) = index.get(&key) {
+            // 已经有相同的节点，撤销上面对子节点的引用
+            for child in children(&kind) {
+                self.nodes[child].uses -= 1;
+            }
+            return id;
+        }
+        self.nodes.push(DagNode {
+            kind,
+            span,
+            uses: 0,
+        });
+        index.insert(key, self.nodes.len() - 1);
+        return self.nodes.len() - 1;
+    }
+
+    /**
+    翻译为逆波兰式，每个公共子表达式只计算一次。
+
+    公共子表达式第一次算出后保存到临时变量，之后直接取出；
+    保存后紧接着就要再用一次时改为复制栈顶，如 `(a+b)*(a+b)` 翻译为 `a b + dup *` 。
+     */
+    pub fn rpn(&self) -> Vec<DagItem> {
+        let mut output = vec![];
+        let mut temps = HashMap::new();
+        self.rpn_into(self.root(), &mut output, &mut temps);
+        return use_dup(output);
+    }
+
+    fn rpn_into(&self, id: usize, output: &mut Vec<DagItem>, temps: &mut HashMap<usize, usize>) {
+        if let Some(&temp) = temps.get(&id) {
+            // 已经算过，直接取出
+            output.push(DagItem::Load(temp));
+            return;
+        }
+
+        let node = &self.nodes[id];
+        let span = node.span;
+        let item = match &node.kind {
+            DagKind::Number(value) => RPNItem::Number {
+                value: value.clone(),
+                span,
+            },
+            DagKind::Variable(name) => RPNItem::Variable {
+                name: name.clone(),
+                span,
+            },
+            DagKind::Unary { op, operand } => {
+                self.rpn_into(*operand, output, temps);
+                RPNItem::Operator { op: *op, span }
+            }
+            DagKind::Binary { op, left, right } => {
+                self.rpn_into(*left, output, temps);
+                self.rpn_into(*right, output, temps);
+                RPNItem::Operator { op: *op, span }
+            }
+            DagKind::Call { name, args } => {
+                for arg in args {
+                    self.rpn_into(*arg, output, temps);
+                }
+                RPNItem::Call {
+                    name: name.clone(),
+                    arity: args.len(),
+                    span,
+                }
+            }
+        };
+        output.push(DagItem::Item(item));
+
+        if !is_leaf(&node.kind) && node.uses > 1 {
+            // 公共子表达式算出后保存起来
+            let temp = temps.len() + 1;
+            temps.insert(id, temp);
+            output.push(DagItem::Store(temp));
+        }
+    }
+
+    /** 翻译为四元式，每个公共子表达式只计算一次，临时变量从 `t1` 开始编号。 */
+    pub fn quadruples(&self) -> Vec<Quadruple> {
+        let mut output = vec![];
+        let mut temps = HashMap::new();
+        self.quadruples_into(self.root(), &mut output, &mut temps);
+        return output;
+    }
+
+    fn quadruples_into(
+        &self,
+        id: usize,
+        output: &mut Vec<Quadruple>,
+        temps: &mut HashMap<usize, usize>,
+    ) -> Operand {
+        if let Some(&temp) = temps.get(&id) {
+            return Operand::Temp(temp);
+        }
+
+        let node = &self.nodes[id];
+        let span = node.span;
+        let (op, arg1, arg2) = match &node.kind {
+            DagKind::Number(value) => {
+                return Operand::Number {
+                    value: value.clone(),
+                    span,
+                }
+            }
+            DagKind::Variable(name) => {
+                return Operand::Variable {
+                    name: name.clone(),
+                    span,
+                }
+            }
+            DagKind::Unary { op, operand } => {
+                let arg = self.quadruples_into(*operand, output, temps);
+                (QuadOp::Operator(*op), Some(arg), None)
+            }
+            DagKind::Binary { op, left, right } => {
+                let left = self.quadruples_into(*left, output, temps);
+                let right = self.quadruples_into(*right, output, temps);
+                (QuadOp::Operator(*op), Some(left), Some(right))
+            }
+            DagKind::Call { name, args } => {
+                let args: Vec<Operand> = args
+                    .iter()
+                    .map(|arg| self.quadruples_into(*arg, output, temps))
+                    .collect();
+                let arity = args.len();
+                for arg in args {
+                    output.push(Quadruple {
+                        op: QuadOp::Param,
+                        arg1: Some(arg),
+                        arg2: None,
+                        result: None,
+                        span,
+                    });
+                }
+                let name = name.clone();
+                (QuadOp::Call { name, arity }, None, None)
+            }
+        };
+
+        let temp = temps.len() + 1;
+        temps.insert(id, temp);
+        output.push(Quadruple {
+            op,
+            arg1,
+            arg2,
+            result: Some(Operand::Temp(temp)),
+            span,
+        });
+        return Operand::Temp(temp);
+    }
+}
+
+/// 子节点的编号
+fn children(kind: &DagKind) -> Vec<usize> {
+    match kind {
+        DagKind::Number(_) | DagKind::Variable(_) => vec![],
+        DagKind::Unary { operand, .. } => vec![*operand],
+        DagKind::Binary { left, right, .. } => vec![*left, *right],
+        DagKind::Call { args, .. } => args.clone(),
+    }
+}
+
+/// 判断两个节点是否相同的键，子节点已经合并过，所以比较子节点的编号就够了
+fn node_key(kind: &DagKind) -> String {
+    match kind {
+        // 1.0 和 1 是同一个数
+        DagKind::Number(n) => format!("{}", n.normalized()),
+        DagKind::Variable(name) => format!("${}", name),
+        DagKind::Unary { op, operand } => format!("{} #{}", op, operand),
+        DagKind::Binary { op, left, right } => format!("{} #{} #{}", op, left, right),
+        DagKind::Call { name, args } => {
+            let args: Vec<String> = args.iter().map(|arg| format!("#{}", arg)).collect();
+            format!("{}({})", name, args.join(" "))
+        }
+    }
+}
+
+fn is_leaf(kind: &DagKind) -> bool {
+    return matches!(kind, DagKind::Number(_) | DagKind::Variable(_));
+}
+
+fn is_operation(item: &RPNItem) -> bool {
+    return matches!(item, RPNItem::Operator { .. } | RPNItem::Call { .. });
+}
+
+/// 把保存后紧接着的取出改为复制栈顶，不再被取出的临时变量不用保存，最后重新给临时变量编号
+fn use_dup(items: Vec<DagItem>) -> Vec<DagItem> {
+    let mut output: Vec<DagItem> = vec![];
+    for item in items {
+        match (output.last(), &item) {
+            (Some(DagItem::Store(stored)), DagItem::Load(temp)) if stored == temp => {
+                output.push(DagItem::Dup);
+            }
+            _ => output.push(item),
+        }
+    }
+
+    let mut loads: HashMap<usize, usize> = HashMap::new();
+    for item in &output {
+        if let DagItem::Load(temp) = item {
+            *loads.entry(*temp).or_default() += 1;
+        }
+    }
+
+    let mut numbers = HashMap::new();
+    let mut result = vec![];
+    for item in output {
+        match item {
+            DagItem::Store(temp) if !loads.contains_key(&temp) => {}
+            DagItem::Store(temp) => {
+                let number = numbers.len() + 1;
+                numbers.insert(temp, number);
+                result.push(DagItem::Store(number));
+            }
+            DagItem::Load(temp) => result.push(DagItem::Load(numbers[&temp])),
+            item => result.push(item),
+        }
+    }
+    return result;
+}
+
+/**
+计算消除公共子表达式后的逆波兰式。
+
+普通的元素和 [`crate::calculate::calculate_with_options`] 共用同一份实现，所以结果相同。
+
+* `exp` [`Dag::rpn`] 翻译出来的逆波兰式
+* `env` 计算环境，变量从这里取值
+* `options` 计算的选项
+ */
+pub fn calculate_dag<N: Numeric>(
+    exp: &[DagItem],
+    env: &Env<N>,
+    options: &EvalOptions,
+) -> Result<N, CalcError> {
+    let mut stack: VecDeque<N> = VecDeque::new();
+    let mut temps: HashMap<usize, N> = HashMap::new();
+
+    for item in exp {
+        match item {
+            DagItem::Item(item) => execute(item, &mut stack, env, options)?,
+            // 复制和保存之前一定刚算出了一个数字
+            DagItem::Dup => stack.push_back(stack.back().unwrap().clone()),
+            DagItem::Store(temp) => {
+                temps.insert(*temp, stack.back().unwrap().clone());
+            }
+            DagItem::Load(temp) => stack.push_back(temps[temp].clone()),
+        }
+    }
+
+    if stack.len() == 1 {
+        return Ok(stack.pop_back().unwrap());
+    }
+    return Err(CalcError::LeftoverOperands(stack.len()));
+}
+
+#[cfg(test)]
+mod test {
+    use super::{calculate_dag, Dag};
+    use crate::calculate::{calculate_with_options, Env};
+    use crate::lower::lower;
+    use crate::options::EvalOptions;
+    use crate::parse::parse;
+    use crate::quadruple::execute_quadruples;
+    use crate::token::tokenization;
+    use crate::token_render::*;
+
+    #[test]
+    fn dag_rpn_test() {
+        // 测试消除公共子表达式后的逆波兰式和省下的运算次数
+        let input_vec = vec![
+            ("(a+b)*(a+b)", "a b + dup *", 1, 2, 3),
+            (
+                "(a+b)*c + (a+b)/c",
+                "a b + store:t1 c * load:t1 c / +",
+                1,
+                4,
+                5,
+            ),
+            ("x*x + 1", "x x * 1 +", 0, 2, 2),
+            (
+                "sqrt(a*a) - sqrt(a*a) + a*a",
+                "a a * store:t1 sqrt:1 dup - load:t1 +",
+                2,
+                4,
+                7,
+            ),
+            ("(-(x^2)) * (-(x^2.0))", "x 2 ^ @ dup *", 1, 3, 5),
+        ];
+        for (input, expected, shared, operations, naive) in input_vec {
+            let tokens = tokenization(input).unwrap();
+            let expr = parse(&mut TokenRender::new_with_tokens(tokens)).unwrap();
+            let dag = Dag::new(&expr);
+            let rpn = dag
+                .rpn()
+                .iter()
+                .map(|item| item.to_string())
+                .collect::<Vec<_>>()
+                .join(" ");
+            assert_eq!(rpn, expected, "input: {}", input);
+            assert_eq!(dag.shared_count(), shared, "input: {}", input);
+            assert_eq!(dag.operation_count(), operations, "input: {}", input);
+            assert_eq!(dag.naive_operation_count(), naive, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn dag_quadruples_test() {
+        // 测试每个公共子表达式只算一次的四元式
+        let tokens = tokenization("(a+b)*(a+b) + max(a+b, 1)").unwrap();
+        let expr = parse(&mut TokenRender::new_with_tokens(tokens)).unwrap();
+        let output: Vec<String> = Dag::new(&expr)
+            .quadruples()
+            .iter()
+            .map(|q| q.to_string())
+            .collect();
+        let expected = vec![
+            "(+, a, b, t1)",
+            "(*, t1, t1, t2)",
+            "(param, t1, _, _)",
+            "(param, 1, _, _)",
+            "(call, max, 2, t3)",
+            "(+, t2, t3, t4)",
+        ];
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn calculate_dag_test() {
+        // 测试两种形式的计算结果和普通的逆波兰式相同
+        let input_vec = vec![
+            "(a+b)*(a+b)",
+            "(a+b)*c + (a+b)/c",
+            "sqrt(a*a) - sqrt(a*a) + a*a",
+            "max(a-b, c) % (a-b) + min(a-b, c)",
+            "(a - b) / (c - c)",
+        ];
+        let mut env = Env::new();
+        env.set("a", "2.5".parse().unwrap());
+        env.set("b", "-1".parse().unwrap());
+        env.set("c", "3".parse().unwrap());
+        let options = EvalOptions::default();
+        for input in input_vec {
+            let tokens = tokenization(input).unwrap();
+            let expr = parse(&mut TokenRender::new_with_tokens(tokens)).unwrap();
+            let dag = Dag::new(&expr);
+            let expected = calculate_with_options(&lower(&expr), &env, &options);
+            let output = calculate_dag(&dag.rpn(), &env, &options);
+            assert_eq!(output, expected, "input: {}", input);
+
+            let output = execute_quadruples(&dag.quadruples(), &mut env.clone(), &options);
+            assert_eq!(output, expected.map(Some), "input: {}", input);
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use crate::calculate::CalcError;
+use crate::parse::{ParseErr, ParseErrType};
+use crate::rpn::RpnErr;
+use crate::token::{LexerErr, Span};
+use ariadne::{CharSet, Color, Config, Label, Report, ReportKind, Source};
+use std::error::Error;
+use std::fmt;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+
+/**
+出错的阶段。
+
+* `Stage::Lexer` 词法分析阶段
+* `Stage::Parser` 语法分析阶段
+* `Stage::Calculate` 计算阶段
+
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// 词法分析阶段
+    Lexer,
+    /// 语法分析阶段
+    Parser,
+    /// 计算阶段
+    Calculate,
+}
+
+impl Stage {
+    /** 阶段的名字，显示在错误报告的开头。 */
+    pub fn name(&self) -> &'static str {
+        match self {
+            Stage::Lexer => "词法分析阶段",
+            Stage::Parser => "语法分析阶段",
+            Stage::Calculate => "计算阶段",
+        }
+    }
+}
+
+/**
+一处错误。
+
+* `message` 标注在出错位置的说明
+* `span` 出错的位置
+
+ */
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    /// 标注在出错位置的说明
+    pub message: String,
+    /// 出错的位置，输入意外结束时指向输入末尾之后
+    pub span: Span,
+}
+
+/**
+同一个阶段发现的所有错误。
+
+* `stage` 出错的阶段
+* `message` 错误的概要
+* `diagnostics` 每一处错误
+* `input` 出错的输入，用于渲染错误报告
+
+ */
+#[derive(Debug, Clone)]
+pub struct Diagnostics {
+    /// 出错的阶段
+    pub stage: Stage,
+    /// 错误的概要
+    pub message: String,
+    /// 每一处错误，至少有一个
+    pub diagnostics: Vec<Diagnostic>,
+    /// 出错的输入
+    input: String,
+}
+
+impl Diagnostics {
+    /** 把所有词法错误放在同一个报告中。`errors` 不能为空。 */
+    pub fn from_lexer_errors(errors: Vec<LexerErr>, input: &str) -> Diagnostics {
+        let message = if errors.len() == 1 {
+            errors[0].reason.clone()
+        } else {
+            format!("发现 {} 处词法错误", errors.len())
+        };
+        let diagnostics = errors
+            .into_iter()
+            .map(|e| Diagnostic {
+                message: "这不是合法的数字、标识符或者符号".to_owned(),
+                span: Span::new(e.position, e.position + e.original_str.len()),
+            })
+            .collect();
+        return Diagnostics {
+            stage: Stage::Lexer,
+            message,
+            diagnostics,
+            input: input.to_owned(),
+        };
+    }
+
+    /** 把所有语法错误放在同一个报告中。`errors` 不能为空。 */
+    pub fn from_parse_errors(errors: Vec<ParseErr>, input: &str) -> Diagnostics {
+        let message = if errors.len() == 1 {
+            errors[0].reason.clone()
+        } else {
+            format!("发现 {} 处语法错误", errors.len())
+        };
+        let diagnostics = errors
+            .into_iter()
+            .map(|e| match e.err_type {
+                // 未预期的 token
+                ParseErrType::Unexpected(token) => Diagnostic {
+                    message: format!("{}，这是一个{}", e.reason, token.info),
+                    span: token.span(),
+                },
+                // 预期某一个 token 但是却突然终止
+                ParseErrType::Insufficient => Diagnostic {
+                    message: format!("{}，未预期到的结束", e.reason),
+                    span: Span::new(input.len(), input.len() + 1),
+                },
+            })
+            .collect();
+        return Diagnostics {
+            stage: Stage::Parser,
+            message,
+            diagnostics,
+            input: input.to_owned(),
+        };
+    }
+
+    /** 解析逆波兰式文本时的错误，作为语法分析阶段的错误报告。 */
+    pub fn from_rpn_error(err: &RpnErr, input: &str) -> Diagnostics {
+        let diagnostics = match err {
+            RpnErr::InvalidItem { reason, span, .. } => vec![Diagnostic {
+                message: reason.clone(),
+                span: *span,
+            }],
+            RpnErr::StackUnderflow {
+                text,
+                needed,
+                found,
+                span,
+            } => vec![Diagnostic {
+                message: format!(
+                    "{} 需要 {} 个运算对象，运算栈中只有 {} 个",
+                    text, needed, found
+                ),
+                span: *span,
+            }],
+            RpnErr::LeftoverOperands { spans } => spans
+                .iter()
+                .map(|span| Diagnostic {
+                    message: "这个值没有被用到".to_owned(),
+                    span: *span,
+                })
+                .collect(),
+            RpnErr::Empty => vec![Diagnostic {
+                message: "这里需要至少一个数字或者变量".to_owned(),
+                span: Span::new(input.len(), input.len() + 1),
+            }],
+        };
+        return Diagnostics {
+            stage: Stage::Parser,
+            message: err.to_string(),
+            diagnostics,
+            input: input.to_owned(),
+        };
+    }
+
+    /** 计算阶段的错误，错误没有位置时返回 `None` 。 */
+    pub fn from_calc_error(err: &CalcError, input: &str) -> Option<Diagnostics> {
+        let span = err.span()?;
+        return Some(Diagnostics {
+            stage: Stage::Calculate,
+            message: err.to_string(),
+            diagnostics: vec![Diagnostic {
+                message: err.label(),
+                span,
+            }],
+            input: input.to_owned(),
+        });
+    }
+
+    /** 计算阶段的错误，错误没有位置时指向整个输入。 */
+    pub fn from_calc_error_or_input(err: &CalcError, input: &str) -> Diagnostics {
+        return Diagnostics::from_calc_error(err, input).unwrap_or_else(|| Diagnostics {
+            stage: Stage::Calculate,
+            message: err.to_string(),
+            diagnostics: vec![Diagnostic {
+                message: err.label(),
+                span: Span::new(0, input.len()),
+            }],
+            input: input.to_owned(),
+        });
+    }
+
+    /** 用 ariadne 把错误渲染为字符串，和图形界面中显示的一样。 */
+    pub fn render(&self) -> String {
+        // 输入意外结束时错误位置在输入末尾之后，报告的位置退回到最后一个字符
+        let offset = self.diagnostics[0]
+            .span
+            .start
+            .min(self.input.len().saturating_sub(1));
+        let mut report = Report::build(
+            ReportKind::Custom(self.stage.name(), Color::Unset),
+            (),
+            offset,
+        )
+        .with_config(report_config())
+        .with_message(&self.message);
+        for d in &self.diagnostics {
+            report =
+                report.with_label(Label::new(d.span.start..d.span.end).with_message(&d.message));
+        }
+
+        let mut c = Cursor::new(Vec::new());
+        report
+            .finish()
+            .write(Source::from(self.input.as_str()), &mut c)
+            .unwrap();
+        c.seek(SeekFrom::Start(0)).unwrap();
+        let mut out = String::new();
+        c.read_to_string(&mut out).unwrap();
+        return out;
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.render())
+    }
+}
+
+impl Error for Diagnostics {}
+
+/// 错误信息的输出配置
+fn report_config() -> Config {
+    return Config::default()
+        .with_color(false)
+        .with_multiline_arrows(false)
+        .with_char_set(CharSet::Unicode)
+        .with_compact(false)
+        .with_cross_gap(false);
+}
@@ -0,0 +1,256 @@
+use crate::ast::{Expr, ExprKind, Stmt, StmtKind};
+use crate::parse_trace::{ParseTrace, TraceChild, TraceNode};
+use crate::token::Span;
+
+/**
+把语法分析的跟踪记录中的语法树导出为 Graphviz DOT 。
+
+非终结符是椭圆形的节点，叶子是方形的 token 节点，标出 token 的原文本和区间。
+有多条语句时每条语句是一棵单独的树。
+
+* `trace` 语法分析的跟踪记录，见 [`crate::parse::parse_traced`]
+ */
+pub fn parse_tree_to_dot(trace: &ParseTrace) -> String {
+    let mut writer = DotWriter::new("parse_tree");
+    for root in trace.roots() {
+        write_trace_node(&mut writer, None, root);
+    }
+    return writer.finish();
+}
+
+/**
+把一个表达式的抽象语法树导出为 Graphviz DOT 。
+
+运算符和函数调用是椭圆形的节点，数字和变量是方形的叶子，都标出输入中的原文本和区间。
+
+* `expr` 抽象语法树的根节点
+* `input` 语法分析的输入，用于取出节点的原文本
+ */
+pub fn ast_to_dot(expr: &Expr, input: &str) -> String {
+    let mut writer = DotWriter::new("ast");
+    write_expr(&mut writer, None, expr, input);
+    return writer.finish();
+}
+
+/**
+把多条语句的抽象语法树导出为 Graphviz DOT ，每条语句是一棵单独的树。
+
+`let` 和赋值语句的根节点标出变量名，子节点是变量的值。
+
+* `program` 语法分析得到的语句
+* `input` 语法分析的输入，用于取出节点的原文本
+ */
+pub fn program_to_dot(program: &[Stmt], input: &str) -> String {
+    let mut writer = DotWriter::new("ast");
+    for stmt in program {
+        let parent = match &stmt.kind {
+            StmtKind::Let { name, .. } => {
+                let label = label(&format!("let {}", name), stmt.span);
+                Some(writer.node(None, &label, "ellipse"))
+            }
+            StmtKind::Assign { name, .. } => {
+                let label = label(&format!("{} =", name), stmt.span);
+                Some(writer.node(None, &label, "ellipse"))
+            }
+            StmtKind::Expr(_) => None,
+        };
+        write_expr(&mut writer, parent, stmt.value(), input);
+    }
+    return writer.finish();
+}
+
+/**
+生成 DOT 文本的工具，节点按创建的顺序编号为 `n0` 、`n1` ……
+
+* `name` 图的名字
+* `lines` 图中的每一行
+* `count` 已经创建的节点个数
+
+ */
+struct DotWriter {
+    /// 图的名字
+    name: &'static str,
+    /// 图中的每一行
+    lines: Vec<String>,
+    /// 已经创建的节点个数
+    count: usize,
+}
+
+impl DotWriter {
+    fn new(name: &'static str) -> DotWriter {
+        return DotWriter {
+            name,
+            lines: vec![],
+            count: 0,
+        };
+    }
+
+    /// 创建一个节点，有父节点时同时创建从父节点指向它的边，返回节点的编号
+    fn node(&mut self, parent: Option<usize>, label: &str, shape: &str) -> usize {
+        let id = self.count;
+        self.count += 1;
+
+        self.lines.push(format!(
+            "    n{} [label=\"{}\", shape={}];",
+            id, label, shape
+        ));
+        if let Some(parent) = parent {
+            self.lines.push(format!("    n{} -> n{};", parent, id));
+        }
+        return id;
+    }
+
+    fn finish(self) -> String {
+        // ordering=out 让子节点按创建的顺序从左到右排列
+        let mut output = format!("digraph {} {{\n    ordering=out;\n", self.name);
+        for line in self.lines {
+            output.push_str(&line);
+            output.push('\n');
+        }
+        output.push('}');
+        return output;
+    }
+}
+
+/// 节点的标签，第一行是转义后的文本，第二行是区间
+fn label(text: &str, span: Span) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    return format!("{}\\n{}..{}", escaped, span.start, span.end);
+}
+
+fn write_trace_node(writer: &mut DotWriter, parent: Option<usize>, node: &TraceNode) {
+    let id = writer.node(parent, node.rule, "ellipse");
+    for child in &node.children {
+        match child {
+            TraceChild::Rule(node) => write_trace_node(writer, Some(id), node),
+            TraceChild::Terminal { text, span } => {
+                writer.node(Some(id), &label(text, *span), "box");
+            }
+        }
+    }
+}
+
+fn write_expr(writer: &mut DotWriter, parent: Option<usize>, expr: &Expr, input: &str) {
+    // 节点在输入中的原文本
+    let text = |span: Span| &input[span.start..span.end];
+
+    match &expr.kind {
+        ExprKind::Number(_) | ExprKind::Variable(_) => {
+            writer.node(parent, &label(text(expr.span), expr.span), "box");
+        }
+        ExprKind::Unary {
+            op_span, operand, ..
+        } => {
+            let id = writer.node(parent, &label(text(*op_span), *op_span), "ellipse");
+            write_expr(writer, Some(id), operand, input);
+        }
+        ExprKind::Binary {
+            op_span,
+            left,
+            right,
+            ..
+        } => {
+            let id = writer.node(parent, &label(text(*op_span), *op_span), "ellipse");
+            write_expr(writer, Some(id), left, input);
+            write_expr(writer, Some(id), right, input);
+        }
+        ExprKind::Call {
+            name,
+            name_span,
+            args,
+        } => {
+            let id = writer.node(
+                parent,
+                &label(&format!("{}()", name), *name_span),
+                "ellipse",
+            );
+            for arg in args {
+                write_expr(writer, Some(id), arg, input);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ast_to_dot, parse_tree_to_dot, program_to_dot};
+    use crate::parse::{parse, parse_program, parse_traced};
+    use crate::token::tokenization;
+    use crate::token_render::*;
+
+    #[test]
+    fn ast_to_dot_test() {
+        // 测试左结合的乘除和括号中的负号
+        let input = "3*4*5/(-2)";
+        let tokens = tokenization(input).unwrap();
+        let expr = parse(&mut TokenRender::new_with_tokens(tokens)).unwrap();
+        let expected = r#"digraph ast {
+    ordering=out;
+    n0 [label="/\n5..6", shape=ellipse];
+    n1 [label="*\n3..4", shape=ellipse];
+    n0 -> n1;
+    n2 [label="*\n1..2", shape=ellipse];
+    n1 -> n2;
+    n3 [label="3\n0..1", shape=box];
+    n2 -> n3;
+    n4 [label="4\n2..3", shape=box];
+    n2 -> n4;
+    n5 [label="5\n4..5", shape=box];
+    n1 -> n5;
+    n6 [label="-\n7..8", shape=ellipse];
+    n0 -> n6;
+    n7 [label="2\n8..9", shape=box];
+    n6 -> n7;
+}"#;
+        assert_eq!(ast_to_dot(&expr, input), expected);
+    }
+
+    #[test]
+    fn program_to_dot_test() {
+        // 测试语句的根节点和函数调用
+        let input = "let x = max(1, 2)";
+        let tokens = tokenization(input).unwrap();
+        let program = parse_program(&mut TokenRender::new_with_tokens(tokens)).unwrap();
+        let expected = r#"digraph ast {
+    ordering=out;
+    n0 [label="let x\n0..17", shape=ellipse];
+    n1 [label="max()\n8..11", shape=ellipse];
+    n0 -> n1;
+    n2 [label="1\n12..13", shape=box];
+    n1 -> n2;
+    n3 [label="2\n15..16", shape=box];
+    n1 -> n3;
+}"#;
+        assert_eq!(program_to_dot(&program, input), expected);
+    }
+
+    #[test]
+    fn parse_tree_to_dot_test() {
+        // 测试语法树的非终结符和叶子
+        let tokens = tokenization("-2").unwrap();
+        let (result, trace) = parse_traced(&mut TokenRender::new_with_tokens(tokens));
+        assert!(result.is_ok());
+        let expected = r#"digraph parse_tree {
+    ordering=out;
+    n0 [label="a", shape=ellipse];
+    n1 [label="m1", shape=ellipse];
+    n0 -> n1;
+    n2 [label="at1", shape=ellipse];
+    n1 -> n2;
+    n3 [label="-\n0..1", shape=box];
+    n2 -> n3;
+    n4 [label="p", shape=ellipse];
+    n2 -> n4;
+    n5 [label="f", shape=ellipse];
+    n4 -> n5;
+    n6 [label="num", shape=ellipse];
+    n5 -> n6;
+    n7 [label="2\n1..2", shape=box];
+    n6 -> n7;
+}"#;
+        assert_eq!(parse_tree_to_dot(&trace), expected);
+    }
+}
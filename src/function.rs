@@ -0,0 +1,557 @@
+use crate::options::{EvalOptions, RoundingMode};
+use bigdecimal::num_bigint::BigInt;
+use bigdecimal::{BigDecimal, One, Signed, ToPrimitive, Zero};
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+
+type Num = BigDecimal;
+
+/// 超越函数计算时额外保留的位数，避免中间结果的截断误差影响最终结果
+const GUARD_DIGITS: i64 = 10;
+
+/// exp 允许的最大参数绝对值，避免算出天文数字卡死程序
+const MAX_EXP_ARGUMENT: i64 = 10000;
+
+/// sin 和 cos 允许的参数的最大整数位数，参数越大归约需要的 π 位数越多
+const MAX_TRIGONOMETRIC_DIGITS: i64 = 1000;
+
+/**
+内置函数。
+
+* `min_arity` 最少参数个数
+* `max_arity` 最多参数个数，`None` 表示不限
+* `call` 函数实现，参数为实参列表和计算的选项，按精度计算的函数的结果按选项中的位数和舍入方式舍入
+ */
+pub struct Function {
+    /// 最少参数个数
+    pub min_arity: usize,
+    /// 最多参数个数，`None` 表示不限
+    pub max_arity: Option<usize>,
+    /// 函数实现，参数为实参列表和计算的选项
+    pub call: fn(&[Num], &EvalOptions) -> Result<Num, String>,
+}
+
+impl Function {
+    fn new(
+        min_arity: usize,
+        max_arity: Option<usize>,
+        call: fn(&[Num], &EvalOptions) -> Result<Num, String>,
+    ) -> Function {
+        return Function {
+            min_arity,
+            max_arity,
+            call,
+        };
+    }
+
+    /** 检查函数是否接受 `arity` 个参数。 */
+    pub fn accepts(&self, arity: usize) -> bool {
+        return arity >= self.min_arity && self.max_arity.is_none_or(|max| arity <= max);
+    }
+
+    /** 描述函数接受的参数个数，用于错误信息。 */
+    pub fn arity_description(&self) -> String {
+        match self.max_arity {
+            Some(max) if max == self.min_arity => format!("{} 个", max),
+            Some(max) => format!("{} 到 {} 个", self.min_arity, max),
+            None => format!("至少 {} 个", self.min_arity),
+        }
+    }
+}
+
+// 函数名和 Function 的对应表，包含了所有内置函数
+pub static FUNCTION_LIST: Lazy<HashMap<&'static str, Function>> = Lazy::new(|| {
+    let mut data: HashMap<&'static str, Function> = HashMap::new();
+
+    // 精确计算的函数
+    data.insert(
+        "abs",
+        Function::new(1, Some(1), |args, _| Ok(args[0].abs())),
+    );
+    data.insert("min", Function::new(1, None, |args, _| Ok(min(args))));
+    data.insert("max", Function::new(1, None, |args, _| Ok(max(args))));
+    data.insert(
+        "floor",
+        Function::new(1, Some(1), |args, _| Ok(floor(&args[0]))),
+    );
+    data.insert(
+        "ceil",
+        Function::new(1, Some(1), |args, _| Ok(ceil(&args[0]))),
+    );
+    data.insert("round", Function::new(1, Some(2), round));
+
+    // 按精度计算的函数
+    data.insert("sqrt", Function::new(1, Some(1), sqrt));
+    data.insert("exp", Function::new(1, Some(1), exp));
+    data.insert("ln", Function::new(1, Some(1), ln));
+    data.insert("sin", Function::new(1, Some(1), sin));
+    data.insert("cos", Function::new(1, Some(1), cos));
+
+    // 常数
+    data.insert(
+        "pi",
+        Function::new(0, Some(0), |_, options| {
+            let precision = options.division_scale as i64;
+            Ok(options
+                .rounding
+                .round(&pi(precision + GUARD_DIGITS), precision))
+        }),
+    );
+    data
+});
+
+/// 数字整数部分的位数，用来估计需要额外保留多少位
+fn integer_digits(n: &Num) -> i64 {
+    let (digits, scale) = n.as_bigint_and_exponent();
+    return (digits.to_string().trim_start_matches('-').len() as i64 - scale).max(0);
+}
+
+fn min(args: &[Num]) -> Num {
+    return args.iter().min().unwrap().clone();
+}
+
+fn max(args: &[Num]) -> Num {
+    return args.iter().max().unwrap().clone();
+}
+
+fn floor(n: &Num) -> Num {
+    // with_scale 会向 0 截断，负数需要再减 1
+    let truncated = n.with_scale(0);
+    if n.is_negative() && !n.is_integer() {
+        return truncated - BigDecimal::one();
+    }
+    return truncated;
+}
+
+fn ceil(n: &Num) -> Num {
+    // with_scale 会向 0 截断，正数需要再加 1
+    let truncated = n.with_scale(0);
+    if n.is_positive() && !n.is_integer() {
+        return truncated + BigDecimal::one();
+    }
+    return truncated;
+}
+
+/// 四舍五入，第二个参数为保留的小数位数，默认为 0
+fn round(args: &[Num], _: &EvalOptions) -> Result<Num, String> {
+    let digits = match args.get(1) {
+        None => 0,
+        Some(n) if n.is_integer() => match n.to_i64() {
+            Some(d) if d.abs() <= 1000 => d,
+            _ => return Err("round 保留的位数绝对值不能超过 1000".to_owned()),
+        },
+        Some(n) => {
+            return Err(format!(
+                "round 保留的位数必须为整数，却得到了 {}",
+                n.normalized()
+            ))
+        }
+    };
+
+    // 加上或减去 0.5 个最小单位后向 0 截断
+    let half = BigDecimal::new(BigInt::from(5), digits + 1);
+    let n = &args[0];
+    if n.is_negative() {
+        return Ok((n - half).with_scale(digits));
+    }
+    return Ok((n + half).with_scale(digits));
+}
+
+fn sqrt(args: &[Num], options: &EvalOptions) -> Result<Num, String> {
+    let precision = options.division_scale;
+    let n = &args[0];
+    if n.is_negative() {
+        return Err(format!(
+            "sqrt 的参数不能为负数，却得到了 {}",
+            n.normalized()
+        ));
+    }
+
+    // 把数字放大 10^(2 * scale) 倍后求整数平方根
+    let scale = precision as i64 + GUARD_DIGITS;
+    let (digits, _) = n.with_scale(2 * scale).into_bigint_and_exponent();
+    let root = BigDecimal::new(digits.sqrt(), scale);
+
+    return Ok(options.rounding.round(&root, precision as i64));
+}
+
+fn exp(args: &[Num], options: &EvalOptions) -> Result<Num, String> {
+    let precision = options.division_scale;
+    let n = &args[0];
+    if n.abs() > BigDecimal::from(MAX_EXP_ARGUMENT) {
+        return Err(format!(
+            "exp 的参数绝对值不能超过 {}，却得到了 {}",
+            MAX_EXP_ARGUMENT,
+            n.normalized()
+        ));
+    }
+
+    // 先把参数不断减半到 1 以内，用泰勒级数算出结果后再平方回去
+    let mut reduced = n.abs();
+    let mut halvings = 0;
+    while reduced >= BigDecimal::one() {
+        reduced = reduced.half();
+        halvings += 1;
+    }
+
+    // 结果的整数位数和每次平方带来的误差都需要额外的位数
+    let magnitude = n.abs().to_f64().unwrap_or(0f64) * std::f64::consts::LOG10_E;
+    let scale = precision as i64 + GUARD_DIGITS + halvings + magnitude.ceil() as i64;
+
+    let mut sum = BigDecimal::one();
+    let mut term = BigDecimal::one();
+    let mut k = 1;
+    loop {
+        // term = x^k / k!
+        term = RoundingMode::Truncate.divide(&(term * &reduced), &BigDecimal::from(k), scale);
+        if term.is_zero() {
+            break;
+        }
+        sum += &term;
+        k += 1;
+    }
+
+    for _ in 0..halvings {
+        sum = sum.square().with_scale(scale);
+    }
+
+    if n.is_negative() {
+        // e^(-x) = 1 / e^x
+        sum = RoundingMode::Truncate.divide(&BigDecimal::one(), &sum, scale);
+    }
+
+    return Ok(options.rounding.round(&sum, precision as i64));
+}
+
+/// 反双曲正切 atanh(x) = x + x^3/3 + x^5/5 + ... ，`x` 的绝对值越小收敛越快
+fn atanh(x: &Num, scale: i64) -> Num {
+    let square = x.square().with_scale(scale);
+    let mut power = x.clone();
+    let mut sum = x.clone();
+    let mut k = 1;
+    loop {
+        power = (power * &square).with_scale(scale);
+        if power.is_zero() {
+            break;
+        }
+        sum += RoundingMode::Truncate.divide(&power, &BigDecimal::from(2 * k + 1), scale);
+        k += 1;
+    }
+    return sum;
+}
+
+/// ln(x) = 2 * atanh((x - 1) / (x + 1)) ，`x` 越接近 1 收敛越快
+fn ln_near_one(x: &Num, scale: i64) -> Num {
+    let z =
+        RoundingMode::Truncate.divide(&(x - BigDecimal::one()), &(x + BigDecimal::one()), scale);
+    return atanh(&z, scale).double();
+}
+
+fn ln(args: &[Num], options: &EvalOptions) -> Result<Num, String> {
+    let precision = options.division_scale;
+    let n = &args[0];
+    if !n.is_positive() {
+        return Err(format!("ln 的参数必须为正数，却得到了 {}", n.normalized()));
+    }
+
+    // 把参数写成 m * 10^e ，其中 0.1 <= m < 1
+    let (digits, digits_scale) = n.as_bigint_and_exponent();
+    let digit_count = digits.to_string().len() as i64;
+    let exponent = digit_count - digits_scale;
+    let mut mantissa = BigDecimal::new(digits, digit_count);
+
+    // 再把 m 乘 2 直到 0.5 <= m < 1 ，让级数收敛得更快
+    let mut doublings = 0;
+    while mantissa < BigDecimal::new(BigInt::from(5), 1) {
+        mantissa = mantissa.double();
+        doublings += 1;
+    }
+
+    // e * ln10 会放大 ln10 的误差，需要额外的位数
+    let scale = precision as i64 + GUARD_DIGITS + exponent.abs().to_string().len() as i64;
+    let ln2 = ln_near_one(&BigDecimal::from(2), scale);
+    // ln10 = 3 * ln2 + ln(10 / 8)
+    let ln10 = ln2.clone() * BigDecimal::from(3)
+        + ln_near_one(&BigDecimal::new(BigInt::from(125), 2), scale);
+
+    // ln(x) = ln(m) - doublings * ln2 + e * ln10
+    let result = ln_near_one(&mantissa, scale) - ln2 * BigDecimal::from(doublings)
+        + ln10 * BigDecimal::from(exponent);
+
+    return Ok(options.rounding.round(&result, precision as i64));
+}
+
+/// 反正切 atan(x) = x - x^3/3 + x^5/5 - ... ，只用于计算 π ，`x` 都很小
+fn atan(x: &Num, scale: i64) -> Num {
+    let square = x.square().with_scale(scale);
+    let mut power = x.clone();
+    let mut sum = x.clone();
+    let mut k = 1;
+    loop {
+        power = -(power * &square).with_scale(scale);
+        if power.is_zero() {
+            break;
+        }
+        sum += RoundingMode::Truncate.divide(&power, &BigDecimal::from(2 * k + 1), scale);
+        k += 1;
+    }
+    return sum;
+}
+
+/// 用梅钦公式 π = 16 * atan(1/5) - 4 * atan(1/239) 计算 π
+fn pi(scale: i64) -> Num {
+    let one = BigDecimal::one();
+    let a = atan(
+        &RoundingMode::Truncate.divide(&one, &BigDecimal::from(5), scale),
+        scale,
+    );
+    let b = atan(
+        &RoundingMode::Truncate.divide(&one, &BigDecimal::from(239), scale),
+        scale,
+    );
+    return a * BigDecimal::from(16) - b * BigDecimal::from(4);
+}
+
+/**
+把三角函数的参数归约到 [-π, π] 内。
+
+返回归约后的参数和计算使用的小数位数。
+ */
+fn reduce_angle(n: &Num, precision: u64, name: &str) -> Result<(Num, i64), String> {
+    let digits = integer_digits(n);
+    if digits > MAX_TRIGONOMETRIC_DIGITS {
+        return Err(format!(
+            "{} 的参数整数部分不能超过 {} 位",
+            name, MAX_TRIGONOMETRIC_DIGITS
+        ));
+    }
+
+    // 归约时 π 的误差会被参数的整数部分放大，需要额外的位数
+    let scale = precision as i64 + GUARD_DIGITS + digits;
+    let pi = pi(scale);
+    let two_pi = pi.double();
+
+    // r = x - 2π * floor(x / 2π) ，此时 0 <= r < 2π
+    let turns = floor(&RoundingMode::Truncate.divide(n, &two_pi, scale));
+    let mut reduced = (n - &two_pi * turns).with_scale(scale);
+    if reduced > pi {
+        reduced -= two_pi;
+    }
+
+    return Ok((reduced, scale));
+}
+
+fn sin(args: &[Num], options: &EvalOptions) -> Result<Num, String> {
+    let precision = options.division_scale;
+    let (x, scale) = reduce_angle(&args[0], precision, "sin")?;
+
+    // sin(x) = x - x^3/3! + x^5/5! - ...
+    let square = x.square().with_scale(scale);
+    let mut term = x.clone();
+    let mut sum = x;
+    let mut k = 1;
+    loop {
+        term = -RoundingMode::Truncate.divide(
+            &(term * &square),
+            &BigDecimal::from((2 * k) * (2 * k + 1)),
+            scale,
+        );
+        if term.is_zero() {
+            break;
+        }
+        sum += &term;
+        k += 1;
+    }
+
+    return Ok(options.rounding.round(&sum, precision as i64));
+}
+
+fn cos(args: &[Num], options: &EvalOptions) -> Result<Num, String> {
+    let precision = options.division_scale;
+    let (x, scale) = reduce_angle(&args[0], precision, "cos")?;
+
+    // cos(x) = 1 - x^2/2! + x^4/4! - ...
+    let square = x.square().with_scale(scale);
+    let mut term = BigDecimal::one();
+    let mut sum = BigDecimal::one();
+    let mut k = 1;
+    loop {
+        term = -RoundingMode::Truncate.divide(
+            &(term * &square),
+            &BigDecimal::from((2 * k - 1) * (2 * k)),
+            scale,
+        );
+        if term.is_zero() {
+            break;
+        }
+        sum += &term;
+        k += 1;
+    }
+
+    return Ok(options.rounding.round(&sum, precision as i64));
+}
+
+#[cfg(test)]
+mod test {
+    use crate::calculate::{calculate, calculate_with_options, CalcError, Env};
+    use crate::lower::lower;
+    use crate::options::{EvalOptions, RoundingMode};
+    use crate::parse::parse;
+    use crate::token::tokenization;
+    use crate::token_render::TokenRender;
+    use bigdecimal::BigDecimal;
+    use std::str::FromStr;
+
+    /// 按默认的选项计算表达式
+    fn calc(input: &str) -> Result<BigDecimal, CalcError> {
+        let tokens = tokenization(input).unwrap();
+        let expr = parse(&mut TokenRender::new_with_tokens(tokens)).unwrap();
+        return calculate(&lower(&expr));
+    }
+
+    #[test]
+    fn exact_function_test() {
+        // 测试精确计算的函数
+        let input_vec = vec![
+            ("abs(-2.5)", "2.5"),
+            ("abs(3)", "3"),
+            ("min(3)", "3"),
+            ("min(3, -2)", "-2"),
+            ("max(3)", "3"),
+            ("max(3, -2)", "3"),
+            ("floor(2.5)", "2"),
+            ("floor(-2.5)", "-3"),
+            ("floor(-2)", "-2"),
+            ("ceil(2.5)", "3"),
+            ("ceil(-2.5)", "-2"),
+            ("ceil(2)", "2"),
+            ("round(2.5)", "3"),
+            ("round(-2.5)", "-3"),
+            ("round(2.345, 2)", "2.35"),
+            ("round(-2.345, 2)", "-2.35"),
+            ("round(1234, -2)", "1200"),
+        ];
+        for (input, expected) in input_vec {
+            let expected = BigDecimal::from_str(expected).unwrap();
+            assert_eq!(calc(input), Ok(expected), "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn precision_function_test() {
+        // 测试按默认的 64 位小数计算的函数，结果向 0 截断
+        let input_vec = vec![
+            (
+                "sqrt(2)",
+                "1.4142135623730950488016887242096980785696718753769480731766797379",
+            ),
+            ("sqrt(16)", "4"),
+            (
+                "exp(1)",
+                "2.7182818284590452353602874713526624977572470936999595749669676277",
+            ),
+            (
+                "exp(-1)",
+                "0.3678794411714423215955237701614608674458111310317678345078368016",
+            ),
+            (
+                "ln(2)",
+                "0.6931471805599453094172321214581765680755001343602552541206800094",
+            ),
+            (
+                "ln(0.5)",
+                "-0.6931471805599453094172321214581765680755001343602552541206800094",
+            ),
+            (
+                "sin(1)",
+                "0.8414709848078965066525023216302989996225630607983710656727517099",
+            ),
+            (
+                "sin(-2)",
+                "-0.9092974268256816953960198659117448427022549714478902683789730115",
+            ),
+            (
+                "cos(1)",
+                "0.5403023058681397174009366074429766037323104206179222276700972553",
+            ),
+            (
+                "pi()",
+                "3.1415926535897932384626433832795028841971693993751058209749445923",
+            ),
+        ];
+        for (input, expected) in input_vec {
+            let expected = BigDecimal::from_str(expected).unwrap();
+            assert_eq!(calc(input), Ok(expected), "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn function_rounding_test() {
+        // 测试按精度计算的函数按选项中的舍入方式舍入，依次为 Truncate HalfUp HalfEven Floor Ceiling
+        let input_vec = vec![
+            (
+                "pi()",
+                4,
+                ["3.1415", "3.1416", "3.1416", "3.1415", "3.1416"],
+            ),
+            ("sqrt(2)", 3, ["1.414", "1.414", "1.414", "1.414", "1.415"]),
+            ("exp(1)", 2, ["2.71", "2.72", "2.72", "2.71", "2.72"]),
+            ("ln(2)", 2, ["0.69", "0.69", "0.69", "0.69", "0.7"]),
+            ("sin(-2)", 2, ["-0.9", "-0.91", "-0.91", "-0.91", "-0.9"]),
+            ("cos(1)", 3, ["0.54", "0.54", "0.54", "0.54", "0.541"]),
+            ("sqrt(2.25)", 0, ["1", "2", "2", "1", "2"]),
+        ];
+        for (input, scale, expected) in input_vec {
+            let tokens = tokenization(input).unwrap();
+            let rpn = lower(&parse(&mut TokenRender::new_with_tokens(tokens)).unwrap());
+            for (rounding, expected) in RoundingMode::ALL.iter().zip(expected) {
+                let options = EvalOptions {
+                    division_scale: scale,
+                    rounding: *rounding,
+                    ..EvalOptions::default()
+                };
+                let result: BigDecimal =
+                    calculate_with_options(&rpn, &Env::default(), &options).unwrap();
+                assert_eq!(
+                    result.normalized().to_string(),
+                    expected,
+                    "input: {}, rounding: {}",
+                    input,
+                    rounding
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn function_test_fail() {
+        // 测试参数个数不对
+        let input_vec = vec![
+            "abs()",
+            "abs(1, 2)",
+            "min()",
+            "floor(1, 2)",
+            "ceil()",
+            "round(1, 2, 3)",
+            "sqrt(4, 2)",
+            "pi(1)",
+        ];
+        for input in input_vec {
+            assert!(
+                matches!(calc(input), Err(CalcError::ArityMismatch { .. })),
+                "input: {}",
+                input
+            );
+        }
+
+        // 测试参数超出定义域
+        let input_vec = vec!["sqrt(-1)", "ln(0)", "ln(-1)", "round(1, 0.5)"];
+        for input in input_vec {
+            assert!(
+                matches!(calc(input), Err(CalcError::DomainError { .. })),
+                "input: {}",
+                input
+            );
+        }
+    }
+}
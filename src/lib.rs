@@ -0,0 +1,696 @@
+/*!
+一个简单的表达式编译器：词法分析、语法分析、翻译为逆波兰式并计算。
+
+最简单的用法是调用 [`evaluate`] ，它依次执行每个阶段，出错时返回和图形界面中一样的错误报告：
+
+```
+let evaluation = toy_parse::evaluate("let x = 2; x ^ 10").unwrap();
+assert_eq!(evaluation.output_text(), "x = 2 。\n计算结果为: 1024 。");
+```
+
+也可以单独使用每个阶段：[`token::tokenization`] 分词，[`parse::parse_program`] 语法分析，
+[`lower::lower`] 翻译为逆波兰式，[`calculate::calculate`] 计算逆波兰式。
+ */
+
+/// 抽象语法树的模块
+pub mod ast;
+
+/// 把逆波兰式编译为字节码并执行的模块
+pub mod bytecode;
+
+/// 计算逆波兰式的模块
+pub mod calculate;
+
+/// 错误报告的模块
+pub mod diagnostics;
+
+/// 消除公共子表达式的模块
+pub mod dag;
+
+/// 导出 Graphviz DOT 的模块
+pub mod dot;
+
+/// 内置函数的模块
+pub mod function;
+
+/// 数字类型的模块
+pub mod numeric;
+
+/// 计算选项的模块
+pub mod options;
+
+/// 把抽象语法树翻译为逆波兰式、波兰式和完全加括号的中缀式的模块
+pub mod lower;
+
+/// 化简表达式的模块
+pub mod optimize;
+
+/// 语法分析的模块
+pub mod parse;
+
+/// 记录语法分析过程的模块
+pub mod parse_trace;
+
+/// 按顺序执行多条语句的模块
+pub mod program;
+
+/// 生成四元式和三元式并解释执行的模块
+pub mod quadruple;
+
+/// 解析逆波兰式文本的模块
+pub mod rpn;
+
+/// 词法分析的模块
+pub mod token;
+
+/// 储存词法分析的结果的结构
+pub mod token_render;
+
+pub use calculate::{DivisionMode, Env};
+pub use diagnostics::{Diagnostic, Diagnostics, Stage};
+pub use num_rational::BigRational;
+pub use numeric::Numeric;
+pub use options::{Backend, EvalOptions, FractionStyle, Notation, RoundingMode, MAX_SCALE};
+
+use ast::Stmt;
+use bigdecimal::BigDecimal;
+use calculate::calculate_with_options;
+use dag::Dag;
+use optimize::{optimize_program, Rewrite};
+use parse::{parse_program_recovering, RPNItem};
+use program::{run_program, StmtResult};
+use quadruple::{generate, to_triples, Quadruple};
+use rpn::parse_rpn;
+use token::tokenization_recovering;
+use token_render::TokenRender;
+
+/**
+[`evaluate`] 的结果。
+
+* `statements` 每条语句的执行结果，遇到计算错误时停止执行，最后一条就是出错的语句
+* `program` 执行的所有语句，化简时为化简后的语句，四元式和 DAG 需要时才从它生成
+* `rewrites` 计算前对所有语句做的化简，选项中不化简时为空
+* `options` 计算时使用的选项，输出时也按照它格式化数字
+
+ */
+pub struct Evaluation<N = BigDecimal> {
+    /// 每条语句的执行结果，遇到计算错误时停止执行，最后一条就是出错的语句
+    pub statements: Vec<StmtResult<N>>,
+    /// 执行的所有语句，化简时为化简后的语句，四元式和 DAG 需要时才从它生成
+    pub program: Vec<Stmt>,
+    /// 计算前对所有语句做的化简，选项中不化简时为空
+    pub rewrites: Vec<Rewrite>,
+    /// 计算时使用的选项，输出时也按照它格式化数字
+    pub options: EvalOptions,
+}
+
+impl<N: Numeric> Evaluation<N> {
+    /** 是否所有语句都计算成功。 */
+    pub fn is_success(&self) -> bool {
+        return self.statements.iter().all(|s| s.value.is_ok());
+    }
+
+    /** 最后一条语句的值，有语句计算失败时为 `None` 。 */
+    pub fn value(&self) -> Option<&N> {
+        return self.statements.last()?.value.as_ref().ok();
+    }
+
+    /** 所有语句翻译出来的四元式。 */
+    pub fn quadruples(&self) -> Vec<Quadruple> {
+        return generate(&self.program);
+    }
+
+    /** 每条语句中的表达式消除公共子表达式后得到的 DAG 。 */
+    pub fn dags(&self) -> Vec<Dag> {
+        return self
+            .program
+            .iter()
+            .map(|stmt| Dag::new(stmt.value()))
+            .collect();
+    }
+
+    /** 每条语句的逆波兰式，每条语句占一行。 */
+    pub fn rpn_text(&self) -> String {
+        let lines: Vec<String> = self.statements.iter().map(|s| items_text(&s.rpn)).collect();
+        return lines.join("\n");
+    }
+
+    /** 每条语句的波兰式，每条语句占一行，格式和逆波兰式相同。 */
+    pub fn prefix_text(&self) -> String {
+        let lines: Vec<String> = self
+            .statements
+            .iter()
+            .map(|s| items_text(&s.prefix()))
+            .collect();
+        return lines.join("\n");
+    }
+
+    /** 每条语句的完全加括号的中缀式，每条语句占一行。 */
+    pub fn infix_text(&self) -> String {
+        let lines: Vec<String> = self.statements.iter().map(|s| s.infix()).collect();
+        return lines.join("\n");
+    }
+
+    /** 所有语句的四元式表格，每个四元式占一行，依次为序号、运算、两个运算对象和结果。 */
+    pub fn quadruple_text(&self) -> String {
+        return quadruple_table(&self.quadruples());
+    }
+
+    /** 所有语句的三元式表格，每个三元式占一行，依次为序号、运算和两个运算对象，用 `(序号)` 引用结果。 */
+    pub fn triple_text(&self) -> String {
+        let mut rows = vec![[
+            "序号".to_owned(),
+            "运算".to_owned(),
+            "运算对象 1".to_owned(),
+            "运算对象 2".to_owned(),
+        ]];
+        for (index, triple) in to_triples(&self.quadruples()).iter().enumerate() {
+            let [op, arg1, arg2] = triple.cells();
+            rows.push([format!("({})", index), op, arg1, arg2]);
+        }
+        return table_lines(&rows).join("\n");
+    }
+
+    /**
+    每条语句消除公共子表达式后的逆波兰式、四元式表格和省下的运算次数。
+
+    有多条语句时每条语句前标出是第几条语句，公共子表达式只在同一条语句中查找。
+     */
+    pub fn dag_text(&self) -> String {
+        let dags = self.dags();
+        let mut blocks = vec![];
+        for (index, dag) in dags.iter().enumerate() {
+            let mut lines = vec![];
+            if dags.len() > 1 {
+                lines.push(format!("第 {} 条语句:", index + 1));
+            }
+
+            let rpn: Vec<String> = dag.rpn().iter().map(|item| item.to_string()).collect();
+            lines.push(format!("[ {} ]", rpn.join(" ")));
+            let quadruples = dag.quadruples();
+            if !quadruples.is_empty() {
+                lines.push(quadruple_table(&quadruples));
+            }
+
+            let (operations, naive) = (dag.operation_count(), dag.naive_operation_count());
+            lines.push(format!(
+                "公共子表达式 {} 个，运算 {} 次，原来的逆波兰式运算 {} 次，少算 {} 次",
+                dag.shared_count(),
+                operations,
+                naive,
+                naive - operations
+            ));
+            blocks.push(lines.join("\n"));
+        }
+        return blocks.join("\n\n");
+    }
+
+    /** 计算前做的每一次化简，每次占一行，如 `常量折叠: (2 * 3) => 6` 。 */
+    pub fn rewrite_text(&self) -> String {
+        if self.rewrites.is_empty() {
+            return "没有可以化简的部分".to_owned();
+        }
+        let lines: Vec<String> = self.rewrites.iter().map(|r| r.to_string()).collect();
+        return lines.join("\n");
+    }
+
+    /** 按输出形式选择逆波兰式、波兰式、完全加括号的中缀式、四元式或者三元式。 */
+    pub fn notation_text(&self, notation: Notation) -> String {
+        return match notation {
+            Notation::Postfix => self.rpn_text(),
+            Notation::Prefix => self.prefix_text(),
+            Notation::Infix => self.infix_text(),
+            Notation::Quadruple => self.quadruple_text(),
+            Notation::Triple => self.triple_text(),
+            Notation::Dag => self.dag_text(),
+        };
+    }
+
+    /**
+    每条语句的计算过程表格，每一步占一行，依次为步骤、执行的元素、执行前后的运算栈和算出的值。
+
+    只有选项中记录了计算过程时才有内容，有多条语句时每条语句的表格前标出是第几条语句。
+     */
+    pub fn trace_text(&self) -> String {
+        let options = &self.options;
+        let mut blocks = vec![];
+        for (index, statement) in self.statements.iter().enumerate() {
+            if statement.trace.is_empty() {
+                continue;
+            }
+
+            let mut rows = vec![[
+                "步骤".to_owned(),
+                "元素".to_owned(),
+                "运算前的栈".to_owned(),
+                "运算后的栈".to_owned(),
+                "结果".to_owned(),
+            ]];
+            for (step, trace) in statement.trace.iter().enumerate() {
+                rows.push([
+                    (step + 1).to_string(),
+                    trace.item.to_string(),
+                    stack_text(&trace.before, options),
+                    stack_text(&trace.after, options),
+                    trace.value.format(options),
+                ]);
+            }
+
+            let mut lines = vec![];
+            if self.statements.len() > 1 {
+                lines.push(format!("第 {} 条语句:", index + 1));
+            }
+            lines.extend(table_lines(&rows));
+            blocks.push(lines.join("\n"));
+        }
+        return blocks.join("\n\n");
+    }
+
+    /** 每条语句的计算结果，每条语句占一行，数字按选项中的位数和舍入方式格式化。 */
+    pub fn output_text(&self) -> String {
+        let options = &self.options;
+        let mut lines = vec![];
+        for statement in &self.statements {
+            match (&statement.value, &statement.target) {
+                // 计算成功，格式化数字后输出
+                (Ok(n), Some(name)) => lines.push(format!("{} = {} 。", name, n.format(options))),
+                (Ok(n), None) => lines.push(format!("计算结果为: {} 。", n.format(options))),
+                // 计算失败，输出错误原因
+                (Err(e), _) => lines.push(format!("计算结果为: {} ！", e)),
+            }
+        }
+        return lines.join("\n");
+    }
+}
+
+/// 四元式表格，每个四元式占一行，依次为序号、运算、两个运算对象和结果
+fn quadruple_table(quadruples: &[Quadruple]) -> String {
+    let mut rows = vec![[
+        "序号".to_owned(),
+        "运算".to_owned(),
+        "运算对象 1".to_owned(),
+        "运算对象 2".to_owned(),
+        "结果".to_owned(),
+    ]];
+    for (index, quadruple) in quadruples.iter().enumerate() {
+        let [op, arg1, arg2, result] = quadruple.cells();
+        rows.push([index.to_string(), op, arg1, arg2, result]);
+    }
+    return table_lines(&rows).join("\n");
+}
+
+/// 拼装逆波兰式或者波兰式的字符串，元素之间用空格分隔
+fn items_text(items: &[RPNItem]) -> String {
+    let mut text = String::from("[ ");
+    for item in items {
+        text.push_str(&format!("{} ", item));
+    }
+    text.push(']');
+    return text;
+}
+
+/// 按逆波兰式的格式输出运算栈，栈底在前
+fn stack_text<N: Numeric>(stack: &[N], options: &EvalOptions) -> String {
+    let mut text = String::from("[ ");
+    for n in stack {
+        text.push_str(&format!("{} ", n.format(options)));
+    }
+    text.push(']');
+    return text;
+}
+
+/// 把表格的每一行按列对齐，中文字符按两个字符宽度计算
+fn table_lines<const COLUMNS: usize>(rows: &[[String; COLUMNS]]) -> Vec<String> {
+    let width = |s: &str| {
+        s.chars()
+            .map(|c| if c.is_ascii() { 1 } else { 2 })
+            .sum::<usize>()
+    };
+
+    let mut widths = [0; COLUMNS];
+    for row in rows {
+        for (column, cell) in row.iter().enumerate() {
+            widths[column] = widths[column].max(width(cell));
+        }
+    }
+
+    let mut lines = vec![];
+    for row in rows {
+        let mut line = String::new();
+        for (column, cell) in row.iter().enumerate() {
+            line.push_str(cell);
+            line.push_str(&" ".repeat(widths[column] - width(cell) + 2));
+        }
+        lines.push(line.trim_end().to_owned());
+    }
+    return lines;
+}
+
+/**
+在新的计算环境中使用默认的选项对输入进行分词、语法分析并按顺序执行每条语句。
+
+* `input` 输入的程序
+ */
+pub fn evaluate(input: &str) -> Result<Evaluation, Diagnostics> {
+    return evaluate_with(input, &mut Env::new());
+}
+
+/**
+使用默认的选项对输入进行分词、语法分析并按顺序执行每条语句。
+
+* `input` 输入的程序
+* `env` 计算环境，执行过程中定义的变量会保留在这里
+ */
+pub fn evaluate_with(input: &str, env: &mut Env) -> Result<Evaluation, Diagnostics> {
+    return evaluate_with_options(input, env, EvalOptions::default());
+}
+
+/**
+对输入进行分词、语法分析并按顺序执行每条语句。
+
+计算使用的数字类型由计算环境决定，如使用 `Env::<BigRational>::default()` 时按精确的有理数计算。
+词法或者语法错误时收集同一阶段的所有错误后返回；知道出错位置的计算错误也作为错误返回，
+指出出错的运算符、变量或者函数调用，其他计算错误记录在出错的语句中。
+
+* `input` 输入的程序
+* `env` 计算环境，执行过程中定义的变量会保留在这里
+* `options` 计算和输出的选项
+ */
+pub fn evaluate_with_options<N: Numeric>(
+    input: &str,
+    env: &mut Env<N>,
+    options: EvalOptions,
+) -> Result<Evaluation<N>, Diagnostics> {
+    // 对输入进行分词，出错时继续分词，收集所有错误
+    let (tokens, lexer_errors) = tokenization_recovering(input);
+    if !lexer_errors.is_empty() {
+        return Err(Diagnostics::from_lexer_errors(lexer_errors, input));
+    }
+
+    // 语法分析，出错时继续分析，收集所有错误
+    let program = parse_program_recovering(&mut TokenRender::new_with_tokens(tokens))
+        .map_err(|errors| Diagnostics::from_parse_errors(errors, input))?;
+
+    // 需要时先化简，出错的子表达式不会被折叠，计算时仍然指出原来的位置
+    let (program, rewrites) = if options.optimize {
+        optimize_program::<N>(&program, &options)
+    } else {
+        (program, vec![])
+    };
+
+    // 按顺序执行每条语句
+    let statements = run_program(&program, env, &options);
+    if let Some(Err(e)) = statements.last().map(|s| &s.value) {
+        // 知道出错位置的计算错误和语法错误一样指出出错的位置
+        if let Some(diagnostics) = Diagnostics::from_calc_error(e, input) {
+            return Err(diagnostics);
+        }
+    }
+
+    return Ok(Evaluation {
+        statements,
+        program,
+        rewrites,
+        options,
+    });
+}
+
+/**
+直接计算逆波兰式文本，如 `3 4 + 2 *` ，写法和输出的逆波兰式相同。
+
+出错时的错误报告指向逆波兰式中的元素：无法识别的元素、数字不够用的运算符或者函数调用、
+没有被用到的数字，以及计算出错的元素。
+
+* `input` 逆波兰式文本，元素之间用空白分隔
+* `env` 计算环境，变量从这里取值
+* `options` 计算的选项
+ */
+pub fn evaluate_rpn<N: Numeric>(
+    input: &str,
+    env: &Env<N>,
+    options: &EvalOptions,
+) -> Result<N, Diagnostics> {
+    let items = parse_rpn(input).map_err(|e| Diagnostics::from_rpn_error(&e, input))?;
+    // 解析时已经检查过运算栈，剩下的计算错误一般都知道出错的位置
+    return calculate_with_options(&items, env, options)
+        .map_err(|e| Diagnostics::from_calc_error_or_input(&e, input));
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        evaluate, evaluate_rpn, evaluate_with_options, BigRational, Env, EvalOptions,
+        FractionStyle, Notation, RoundingMode, Stage,
+    };
+
+    #[test]
+    fn evaluate_test() {
+        // 测试每个阶段出错时的结果
+        let input_vec = vec![
+            ("1+2*3", Ok("计算结果为: 7 。")),
+            ("let x = 2; x ^ 10", Ok("x = 2 。\n计算结果为: 1024 。")),
+            ("let x = 2; sqrt(-x)", Err((Stage::Calculate, vec![11..19]))),
+            (
+                "let x = 0; 1 + 2 // x",
+                Err((Stage::Calculate, vec![17..19])),
+            ),
+            ("1+2a+3b", Err((Stage::Lexer, vec![2..4, 5..7]))),
+            ("(1+; 2*", Err((Stage::Parser, vec![3..4, 7..8]))),
+            ("let x = 1; y + x", Err((Stage::Calculate, vec![11..12]))),
+        ];
+        for (input, expected) in input_vec {
+            match (evaluate(input), expected) {
+                (Ok(evaluation), Ok(output)) => {
+                    assert_eq!(evaluation.output_text(), output, "input: {}", input)
+                }
+                (Err(diagnostics), Err((stage, spans))) => {
+                    assert_eq!(diagnostics.stage, stage, "input: {}", input);
+                    let actual = diagnostics
+                        .diagnostics
+                        .iter()
+                        .map(|d| d.span.start..d.span.end)
+                        .collect::<Vec<_>>();
+                    assert_eq!(actual, spans, "input: {}", input);
+                }
+                _ => panic!("input: {}", input),
+            }
+        }
+    }
+
+    #[test]
+    fn evaluate_with_options_test() {
+        // 测试除法精度、输出位数和舍入方式
+        let input_vec = vec![
+            ("2/3", 64, 3, RoundingMode::Truncate, "计算结果为: 0.666 。"),
+            ("2/3", 64, 3, RoundingMode::HalfEven, "计算结果为: 0.667 。"),
+            (
+                "1/3*3",
+                2,
+                15,
+                RoundingMode::Truncate,
+                "计算结果为: 0.99 。",
+            ),
+            ("1/3*3", 2, 1, RoundingMode::HalfUp, "计算结果为: 1 。"),
+            ("-5/2", 0, 15, RoundingMode::Floor, "计算结果为: -3 。"),
+            (
+                "2^(-3)",
+                2,
+                15,
+                RoundingMode::Ceiling,
+                "计算结果为: 0.13 。",
+            ),
+        ];
+        for (input, division_scale, output_scale, rounding, expected) in input_vec {
+            let options = EvalOptions {
+                division_scale,
+                output_scale,
+                rounding,
+                ..EvalOptions::default()
+            };
+            let evaluation = evaluate_with_options(input, &mut Env::new(), options).unwrap();
+            assert_eq!(evaluation.output_text(), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn evaluate_exact_test() {
+        // 测试精确的有理数运算和分数形式的输出
+        let input_vec = vec![
+            ("1/3*3", FractionStyle::Improper, "计算结果为: 1 。"),
+            (
+                "7/3",
+                FractionStyle::Improper,
+                "计算结果为: 2.333333333333333 (7/3) 。",
+            ),
+            (
+                "-7/3",
+                FractionStyle::Mixed,
+                "计算结果为: -2.333333333333333 (-2 1/3) 。",
+            ),
+            ("0.1 + 0.2", FractionStyle::Off, "计算结果为: 0.3 。"),
+            (
+                "let x = 1/6; x * 2",
+                FractionStyle::Improper,
+                "x = 0.166666666666666 (1/6) 。\n计算结果为: 0.333333333333333 (1/3) 。",
+            ),
+            ("2^(-2) // 0.1", FractionStyle::Improper, "计算结果为: 2 。"),
+            (
+                "7 % (2/3)",
+                FractionStyle::Improper,
+                "计算结果为: 0.333333333333333 (1/3) 。",
+            ),
+        ];
+        for (input, fraction, expected) in input_vec {
+            let options = EvalOptions {
+                fraction,
+                ..EvalOptions::default()
+            };
+            let mut env = Env::<BigRational>::default();
+            let evaluation = evaluate_with_options(input, &mut env, options).unwrap();
+            assert_eq!(evaluation.output_text(), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn trace_text_test() {
+        // 测试计算过程表格的对齐和多条语句的标题
+        let options = EvalOptions {
+            trace: true,
+            ..EvalOptions::default()
+        };
+        let evaluation =
+            evaluate_with_options("let x = 10; x / 4", &mut Env::new(), options).unwrap();
+        let expected = "\
+第 1 条语句:
+步骤  元素  运算前的栈  运算后的栈  结果
+1     10    [ ]         [ 10 ]      10
+
+第 2 条语句:
+步骤  元素  运算前的栈  运算后的栈  结果
+1     x     [ ]         [ 10 ]      10
+2     4     [ 10 ]      [ 10 4 ]    4
+3     /     [ 10 4 ]    [ 2.5 ]     2.5";
+        assert_eq!(evaluation.trace_text(), expected);
+
+        // 默认不记录计算过程
+        let evaluation = evaluate("1 + 2").unwrap();
+        assert_eq!(evaluation.trace_text(), "");
+    }
+
+    #[test]
+    fn notation_text_test() {
+        // 测试每种输出形式
+        let evaluation = evaluate("let x = -2; 3*4*5/x").unwrap();
+        let input_vec = vec![
+            (Notation::Postfix, "[ 2 @ ]\n[ 3 4 * 5 * x / ]"),
+            (Notation::Prefix, "[ @ 2 ]\n[ / * * 3 4 5 x ]"),
+            (Notation::Infix, "(@ 2)\n(((3 * 4) * 5) / x)"),
+            (
+                Notation::Quadruple,
+                "\
+序号  运算  运算对象 1  运算对象 2  结果
+0     @     2           _           t1
+1     let   t1          _           x
+2     *     3           4           t2
+3     *     t2          5           t3
+4     /     t3          x           t4",
+            ),
+            (
+                Notation::Triple,
+                "\
+序号  运算  运算对象 1  运算对象 2
+(0)   @     2           _
+(1)   let   x           (0)
+(2)   *     3           4
+(3)   *     (2)         5
+(4)   /     (3)         x",
+            ),
+        ];
+        for (notation, expected) in input_vec {
+            assert_eq!(evaluation.notation_text(notation), expected, "{}", notation);
+        }
+    }
+
+    #[test]
+    fn dag_text_test() {
+        // 测试消除公共子表达式后的输出
+        let evaluation = evaluate("let a = 1; let b = 2; (a+b)*(a+b)").unwrap();
+        let expected = "\
+第 1 条语句:
+[ 1 ]
+公共子表达式 0 个，运算 0 次，原来的逆波兰式运算 0 次，少算 0 次
+
+第 2 条语句:
+[ 2 ]
+公共子表达式 0 个，运算 0 次，原来的逆波兰式运算 0 次，少算 0 次
+
+第 3 条语句:
+[ a b + dup * ]
+序号  运算  运算对象 1  运算对象 2  结果
+0     +     a           b           t1
+1     *     t1          t1          t2
+公共子表达式 1 个，运算 2 次，原来的逆波兰式运算 3 次，少算 1 次";
+        assert_eq!(evaluation.notation_text(Notation::Dag), expected);
+        assert_eq!(evaluation.value(), Some(&9.into()));
+    }
+
+    #[test]
+    fn evaluate_rpn_test() {
+        // 测试直接计算逆波兰式，错误指向逆波兰式中的元素
+        let mut env = Env::new();
+        env.set("x", 5.into());
+        let options = EvalOptions::default();
+        assert_eq!(
+            evaluate_rpn("3 x + 2 *", &env, &options).ok(),
+            Some(16.into())
+        );
+
+        let input_vec = vec![
+            ("3 +", Stage::Parser, vec![2..3]),
+            ("1 2 3 * 4", Stage::Parser, vec![0..1, 6..7]),
+            ("1 x x - /", Stage::Calculate, vec![8..9]),
+            ("y 1 +", Stage::Calculate, vec![0..1]),
+        ];
+        for (input, stage, spans) in input_vec {
+            match evaluate_rpn(input, &env, &options) {
+                Err(diagnostics) => {
+                    assert_eq!(diagnostics.stage, stage, "input: {}", input);
+                    let output: Vec<_> = diagnostics
+                        .diagnostics
+                        .iter()
+                        .map(|d| d.span.start..d.span.end)
+                        .collect();
+                    assert_eq!(output, spans, "input: {}", input);
+                }
+                Ok(_) => panic!("{} 应该出错", input),
+            }
+        }
+    }
+
+    #[test]
+    fn evaluate_optimized_test() {
+        // 测试化简后的逆波兰式和化简的记录
+        let options = EvalOptions {
+            optimize: true,
+            ..EvalOptions::default()
+        };
+        let evaluation =
+            evaluate_with_options("let x = 2; x*1 + 0 + 2*3", &mut Env::new(), options).unwrap();
+        assert_eq!(evaluation.rpn_text(), "[ 2 ]\n[ x 6 + ]");
+        assert_eq!(evaluation.output_text(), "x = 2 。\n计算结果为: 8 。");
+        assert_eq!(
+            evaluation.rewrite_text(),
+            "乘以 1: (x * 1) => x\n加 0: (x + 0) => x\n常量折叠: (2 * 3) => 6"
+        );
+
+        // 除 0 的子表达式不折叠，仍然指出原来的除号
+        match evaluate_with_options("1 + 2 / (3 - 3)", &mut Env::new(), options) {
+            Err(diagnostics) => {
+                assert_eq!(diagnostics.stage, Stage::Calculate);
+                let span = diagnostics.diagnostics[0].span;
+                assert_eq!(span.start..span.end, 6..7);
+            }
+            Ok(_) => panic!("1 + 2 / (3 - 3) 应该出错"),
+        }
+    }
+}
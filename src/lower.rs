@@ -0,0 +1,240 @@
+use crate::ast::{Expr, ExprKind};
+use crate::parse::RPNItem;
+
+/**
+把 AST 翻译为逆波兰式。
+
+后序遍历 AST ，先输出操作数再输出运算符。
+
+* `expr` AST 的根节点
+ */
+pub fn lower(expr: &Expr) -> Vec<RPNItem> {
+    let mut output = vec![];
+    lower_into(expr, &mut output);
+    return output;
+}
+
+fn lower_into(expr: &Expr, output: &mut Vec<RPNItem>) {
+    match &expr.kind {
+        ExprKind::Number(n) => {
+            output.push(RPNItem::Number {
+                value: n.clone(),
+                span: expr.span,
+            });
+        }
+        ExprKind::Variable(name) => {
+            output.push(RPNItem::Variable {
+                name: name.clone(),
+                span: expr.span,
+            });
+        }
+        ExprKind::Unary {
+            op,
+            op_span,
+            operand,
+        } => {
+            lower_into(operand, output);
+            output.push(RPNItem::Operator {
+                op: *op,
+                span: *op_span,
+            });
+        }
+        ExprKind::Binary {
+            op,
+            op_span,
+            left,
+            right,
+        } => {
+            lower_into(left, output);
+            lower_into(right, output);
+            output.push(RPNItem::Operator {
+                op: *op,
+                span: *op_span,
+            });
+        }
+        ExprKind::Call { name, args, .. } => {
+            for arg in args {
+                lower_into(arg, output);
+            }
+            output.push(RPNItem::Call {
+                name: name.clone(),
+                arity: args.len(),
+                span: expr.span,
+            });
+        }
+    }
+}
+
+/**
+把 AST 翻译为波兰式，也就是前缀表达式。
+
+先序遍历 AST ，先输出运算符再输出操作数，元素和逆波兰式相同，取负仍然是 `@` ，
+函数调用仍然带参数个数，如 `max(1, -2)` 翻译为 `max:2 1 @ 2` 。
+
+* `expr` AST 的根节点
+ */
+pub fn lower_prefix(expr: &Expr) -> Vec<RPNItem> {
+    let mut output = vec![];
+    lower_prefix_into(expr, &mut output);
+    return output;
+}
+
+fn lower_prefix_into(expr: &Expr, output: &mut Vec<RPNItem>) {
+    match &expr.kind {
+        ExprKind::Number(_) | ExprKind::Variable(_) => {
+            // 叶子的元素和逆波兰式相同
+            output.extend(lower(expr));
+        }
+        ExprKind::Unary {
+            op,
+            op_span,
+            operand,
+        } => {
+            output.push(RPNItem::Operator {
+                op: *op,
+                span: *op_span,
+            });
+            lower_prefix_into(operand, output);
+        }
+        ExprKind::Binary {
+            op,
+            op_span,
+            left,
+            right,
+        } => {
+            output.push(RPNItem::Operator {
+                op: *op,
+                span: *op_span,
+            });
+            lower_prefix_into(left, output);
+            lower_prefix_into(right, output);
+        }
+        ExprKind::Call { name, args, .. } => {
+            output.push(RPNItem::Call {
+                name: name.clone(),
+                arity: args.len(),
+                span: expr.span,
+            });
+            for arg in args {
+                lower_prefix_into(arg, output);
+            }
+        }
+    }
+}
+
+/**
+把 AST 翻译为完全加括号的中缀式。
+
+每个运算都加上括号，运算符两边各空一格，取负和逆波兰式一样写作 `@` ，
+如 `3*4*5/(-2)` 翻译为 `(((3 * 4) * 5) / (@ 2))` 。
+
+* `expr` AST 的根节点
+ */
+pub fn parenthesize(expr: &Expr) -> String {
+    match &expr.kind {
+        ExprKind::Number(_) | ExprKind::Variable(_) => {
+            // 数字的格式和逆波兰式相同
+            return lower(expr)[0].to_string();
+        }
+        ExprKind::Unary { op, operand, .. } => {
+            return format!("({} {})", op, parenthesize(operand));
+        }
+        ExprKind::Binary {
+            op, left, right, ..
+        } => {
+            return format!("({} {} {})", parenthesize(left), op, parenthesize(right));
+        }
+        ExprKind::Call { name, args, .. } => {
+            let args = args.iter().map(parenthesize).collect::<Vec<_>>();
+            return format!("{}({})", name, args.join(", "));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{lower, lower_prefix, parenthesize};
+    use crate::parse::parse;
+    use crate::token::tokenization;
+    use crate::token_render::*;
+
+    #[test]
+    fn lower_test() {
+        // 测试翻译出来的逆波兰式
+        let input_vec = vec![
+            ("56+88-9999", "56 88 + 9999 -"),
+            ("3-2*1", "3 2 1 * -"),
+            ("3*4*5/(-2)", "3 4 * 5 * 2 @ /"),
+            ("-(2+3)", "2 3 + @"),
+            ("-3+.5", "3 @ 0.5 +"),
+            ("2**3^2", "2 3 2 ^ ^"),
+            ("-2^2*3", "2 2 ^ @ 3 *"),
+            ("max(1, -2, 3+4)", "1 2 @ 3 4 + max:3"),
+            ("-sqrt(2)^2", "2 sqrt:1 2 ^ @"),
+            ("rate*(1+x)", "rate 1 x + *"),
+        ];
+        for (input, expected) in input_vec {
+            let tokens = tokenization(input).unwrap();
+            let expr = parse(&mut TokenRender::new_with_tokens(tokens)).unwrap();
+            let rpn = lower(&expr)
+                .iter()
+                .map(|item| item.to_string())
+                .collect::<Vec<_>>()
+                .join(" ");
+            assert_eq!(rpn, expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn lower_test_span() {
+        // 测试每个逆波兰式元素对应的输入
+        let input_vec = vec![
+            ("3 - 2*1", vec!["3", "2", "1", "*", "-"]),
+            ("-(2 + x)", vec!["2", "x", "+", "-"]),
+            (
+                "2 ** max(1, -y)",
+                vec!["2", "1", "y", "-", "max(1, -y)", "**"],
+            ),
+            ("7 // 2.50", vec!["7", "2.50", "//"]),
+        ];
+        for (input, expected) in input_vec {
+            let tokens = tokenization(input).unwrap();
+            let expr = parse(&mut TokenRender::new_with_tokens(tokens)).unwrap();
+            let substrings = lower(&expr)
+                .iter()
+                .map(|item| &input[item.span().start..item.span().end])
+                .collect::<Vec<_>>();
+            assert_eq!(substrings, expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn lower_prefix_test() {
+        // 测试翻译出来的波兰式和完全加括号的中缀式
+        let input_vec = vec![
+            ("56+88-9999", "- + 56 88 9999", "((56 + 88) - 9999)"),
+            ("3-2*1", "- 3 * 2 1", "(3 - (2 * 1))"),
+            ("3*4*5/(-2)", "/ * * 3 4 5 @ 2", "(((3 * 4) * 5) / (@ 2))"),
+            ("-(2+3)", "@ + 2 3", "(@ (2 + 3))"),
+            ("2**3^2", "^ 2 ^ 3 2", "(2 ^ (3 ^ 2))"),
+            ("-2^2*3", "* @ ^ 2 2 3", "((@ (2 ^ 2)) * 3)"),
+            (
+                "max(1, -2, 3+4)",
+                "max:3 1 @ 2 + 3 4",
+                "max(1, (@ 2), (3 + 4))",
+            ),
+            ("rate*(1+x)", "* rate + 1 x", "(rate * (1 + x))"),
+        ];
+        for (input, prefix, infix) in input_vec {
+            let tokens = tokenization(input).unwrap();
+            let expr = parse(&mut TokenRender::new_with_tokens(tokens)).unwrap();
+            let items = lower_prefix(&expr)
+                .iter()
+                .map(|item| item.to_string())
+                .collect::<Vec<_>>()
+                .join(" ");
+            assert_eq!(items, prefix, "input: {}", input);
+            assert_eq!(parenthesize(&expr), infix, "input: {}", input);
+        }
+    }
+}
@@ -1,145 +1,55 @@
 #![windows_subsystem = "windows"]
 
-use crate::{parse::parse, token::tokenization};
-use ariadne::{CharSet, Color, Config, Label, Report, ReportKind, Source};
-use std::{
-    io::{Cursor, Read, Seek, SeekFrom},
-    rc::Rc,
+use bigdecimal::BigDecimal;
+use std::rc::Rc;
+use toy_parse::{
+    evaluate_with_options, Backend, BigRational, Diagnostics, DivisionMode, Env, EvalOptions,
+    FractionStyle, Notation, Numeric, RoundingMode,
 };
-use token_render::TokenRender;
 
-/// 计算逆波兰式的模块
-mod calculate;
-
-/// 语法分析的模块
-mod parse;
-
-/// 词法分析的模块
-mod token;
-
-/// 储存词法分析的结果的结构
-mod token_render;
-
-fn parse_and_run(input: &str) -> Result<(String, String), String> {
-    // 配置错误信息
-    let mut c = Cursor::new(Vec::new());
-    let config = Config::default()
-        .with_color(false)
-        .with_multiline_arrows(false)
-        .with_char_set(CharSet::Unicode)
-        .with_compact(false)
-        .with_cross_gap(false);
-
-    // 对输入进行分词
-    match tokenization(input) {
-        Ok(t) => {
-            // 分词成功
-            // 语法分析
-            match parse(&mut TokenRender::new_with_tokens(t)) {
-                Ok(v) => {
-                    // 语法分析成功
-
-                    // 拼装字符串输出逆波兰式
-                    let mut rpn_str = String::from("[ ");
-                    for item in &v {
-                        rpn_str.push_str(&format!("{} ", item));
-                    }
-                    rpn_str.push_str("]");
-
-                    // 计算逆波兰式
-                    let result = calculate::calculate(&v);
-                    match result {
-                        Ok(n) => {
-                            // 计算成功，格式化数字后输出
-
-                            return Ok((
-                                rpn_str,
-                                format!(
-                                    "计算结果为: {} 。",
-                                    n.with_scale(15).normalized().to_string()
-                                ),
-                            ));
-                        }
-                        Err(e) => {
-                            // 计算失败，输出结果
+/// 从界面上的计算选项控件读取选项，下拉框按 `RoundingMode::ALL` 、`DivisionMode::ALL` 和 `FractionStyle::ALL` 的顺序排列
+fn read_options(main_window: &MainWindow) -> EvalOptions {
+    let rounding = RoundingMode::ALL
+        .get(main_window.get_rounding_index() as usize)
+        .copied()
+        .unwrap_or(RoundingMode::Truncate);
+    let division_mode = DivisionMode::ALL
+        .get(main_window.get_division_mode_index() as usize)
+        .copied()
+        .unwrap_or(DivisionMode::Floored);
+    let fraction = FractionStyle::ALL
+        .get(main_window.get_fraction_index() as usize)
+        .copied()
+        .unwrap_or(FractionStyle::Improper);
+    return EvalOptions {
+        division_scale: main_window.get_division_scale().max(0) as u64,
+        output_scale: main_window.get_output_scale().max(0) as u64,
+        rounding,
+        division_mode,
+        fraction,
+        // 图形界面总是显示计算过程
+        trace: true,
+        ..EvalOptions::default()
+    };
+}
 
-                            return Ok((rpn_str, format!("计算结果为: {} ！", e)));
-                        }
-                    }
-                }
-                Err(e) => {
-                    // 根据错误类型输出不同的结果
-                    match e.err_type {
-                        parse::ParseErrType::Unexpected(e2) => {
-                            // 未预期的 token
+/// 使用数字类型 `N` 在新的计算环境中计算输入，返回 `notation` 形式的表达式、计算过程和计算结果
+fn evaluate_text<N: Numeric>(
+    input: &str,
+    options: EvalOptions,
+    notation: Notation,
+) -> Result<(String, String, String), Diagnostics> {
+    let evaluation = evaluate_with_options(input, &mut Env::<N>::default(), options)?;
+    return Ok((
+        evaluation.notation_text(notation),
+        evaluation.trace_text(),
+        evaluation.output_text(),
+    ));
+}
 
-                            Report::build(
-                                ReportKind::Custom("语法分析阶段", Color::Unset),
-                                (),
-                                e2.position,
-                            )
-                            .with_config(config)
-                            .with_message(e.reason)
-                            .with_label(
-                                Label::new(e2.position..(e2.position + e2.original_str.len()))
-                                    .with_message(format!("这是一个{}", e2.info)),
-                            )
-                            .finish()
-                            .write(Source::from(input), &mut c)
-                            .unwrap();
-                            c.seek(SeekFrom::Start(0)).unwrap();
-                            let mut out = String::new();
-                            c.read_to_string(&mut out).unwrap();
-                            return Err(out);
-                        }
-                        parse::ParseErrType::Insufficient => {
-                            // 预期某一个 token 但是却突然终止
-                            Report::build(
-                                ReportKind::Custom("语法分析阶段", Color::Unset),
-                                (),
-                                input.len() - 1,
-                            )
-                            .with_config(config)
-                            .with_message(e.reason)
-                            .with_label(
-                                Label::new((input.len())..(input.len() + 1))
-                                    .with_message(format!("未预期到的结束")),
-                            )
-                            .finish()
-                            .write(Source::from(input), &mut c)
-                            .unwrap();
-                            c.seek(SeekFrom::Start(0)).unwrap();
-                            let mut out = String::new();
-                            c.read_to_string(&mut out).unwrap();
-                            return Err(out);
-                        }
-                    }
-                }
-            }
-        }
-        Err(e) => {
-            // 词法分析出错
-            // 根据词法分析的错误输出
-            Report::build(
-                ReportKind::Custom("词法分析阶段", Color::Unset),
-                (),
-                e.position,
-            )
-            .with_config(config)
-            .with_message(e.reason)
-            .with_label(
-                Label::new((e.position)..(e.position + e.original_str.len()))
-                    .with_message(format!("这不是合法的数字或者符号")),
-            )
-            .finish()
-            .write(Source::from(input), &mut c)
-            .unwrap();
-            c.seek(SeekFrom::Start(0)).unwrap();
-            let mut out = String::new();
-            c.read_to_string(&mut out).unwrap();
-            return Err(out);
-        }
-    }
+/// 多行字符串中最长一行的长度
+fn max_line_len(s: &str) -> usize {
+    return s.lines().map(|line| line.len()).max().unwrap_or(0);
 }
 
 slint::include_modules!();
@@ -152,26 +62,51 @@ fn main() {
             old_input = input.to_owned().into();
             if !input.is_empty() {
                 // 如果输入不为空
-                match parse_and_run(input.as_str()) {
-                    Ok((re_polish, output)) => {
+                // 每次都重新执行整个输入，所以使用新的计算环境
+                let options = read_options(&main_window);
+                let backend = Backend::ALL
+                    .get(main_window.get_backend_index() as usize)
+                    .copied()
+                    .unwrap_or(Backend::Decimal);
+                // 下拉框按 `Notation::ALL` 的顺序排列
+                let notation = Notation::ALL
+                    .get(main_window.get_notation_index() as usize)
+                    .copied()
+                    .unwrap_or(Notation::Postfix);
+                let input = input.as_str();
+                let result = match backend {
+                    Backend::Decimal => evaluate_text::<BigDecimal>(input, options, notation),
+                    Backend::Rational => evaluate_text::<BigRational>(input, options, notation),
+                    Backend::Float => evaluate_text::<f64>(input, options, notation),
+                    Backend::Integer => evaluate_text::<i128>(input, options, notation),
+                };
+                match result {
+                    Ok((re_polish, trace, output)) => {
                         main_window.set_output_viewport_x(0f32);
                         main_window.set_re_polish_viewport_x(0f32);
-                        main_window.set_output_width(output.len() as i32 + 20);
-                        main_window.set_re_polish_width(re_polish.len() as i32 + 20);
+                        main_window.set_trace_viewport_x(0f32);
+                        main_window.set_output_width(max_line_len(&output) as i32 + 20);
+                        main_window.set_re_polish_width(max_line_len(&re_polish) as i32 + 20);
+                        main_window.set_trace_width(max_line_len(&trace) as i32 + 20);
                         main_window.set_output_content(output.into());
                         main_window.set_re_polish_content(re_polish.into());
+                        main_window.set_trace_content(trace.into());
                     }
-                    Err(e) => {
-                        main_window.set_output_width(input.len() as i32 + 30);
+                    Err(diagnostics) => {
+                        let e = diagnostics.render();
+                        main_window.set_output_width(max_line_len(&e) as i32 + 30);
                         main_window.set_re_polish_width(15);
                         main_window.set_output_content(e.into());
                         main_window.set_re_polish_content("解析表达式失败！".into());
+                        main_window.set_trace_width(15);
+                        main_window.set_trace_content("".into());
                     }
                 }
             } else {
                 // 如果输入为空
                 main_window.set_output_content("".into());
                 main_window.set_re_polish_content("".into());
+                main_window.set_trace_content("".into());
             }
         } else {
             main_window.set_input_content(old_input.clone().into());
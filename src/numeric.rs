@@ -0,0 +1,521 @@
+use crate::calculate::DivisionMode;
+use crate::options::{ten_to_the, EvalOptions, FractionStyle, RoundingMode};
+use bigdecimal::num_bigint::BigInt;
+use bigdecimal::{BigDecimal, One, Signed, ToPrimitive, Zero};
+use num_rational::BigRational;
+use std::fmt;
+use std::str::FromStr;
+
+/**
+计算时使用的数字类型。
+
+词法分析、语法分析、抽象语法树和逆波兰式都不对数字类型泛型，其中的字面量总是精确的 `BigDecimal` ，
+只有计算的部分（`calculate` 、`bytecode` 、`quadruple` 、`dag` 、`program` 和常量折叠）对 `N` 泛型，
+计算前用 `from_decimal` 把字面量转换为具体的数字类型。这样解析一次得到的结果不需要重新解析，
+就可以按不同的数字类型计算，`0.1` 这样的字面量也不会在解析时就被 `f64` 或者 `i128` 改变。
+内置函数只支持 `BigDecimal` ，调用前后会用 `to_decimal` 和 `from_decimal` 转换。
+
+运算结果超出数字类型的范围时返回 `None` ，如 `i128` 的溢出和 `f64` 算出无穷大。
+调用 `checked_div` 、`checked_int_div` 前需要检查除数不为 0 ，
+调用 `checked_powi` 前需要检查底数为 0 时指数不为负数。
+ */
+pub trait Numeric: Clone + PartialEq + fmt::Debug {
+    /** 数字类型的名字，用于错误信息。 */
+    const NAME: &'static str;
+
+    /** 从精确的十进制数转换，不能表示这个数时返回 `None` ，如 `i128` 不能表示小数。 */
+    fn from_decimal(n: &BigDecimal) -> Option<Self>;
+
+    /** 转换为十进制数，不能精确表示时按 `rounding` 保留小数点后 `scale` 位。 */
+    fn to_decimal(&self, scale: i64, rounding: RoundingMode) -> BigDecimal;
+
+    /** 按选项格式化输出。 */
+    fn format(&self, options: &EvalOptions) -> String;
+
+    /** 是否为 0 。 */
+    fn is_zero(&self) -> bool;
+
+    /** 是整数并且在 `i64` 范围内时返回它的值。 */
+    fn to_i64(&self) -> Option<i64>;
+
+    /** 是否为整数。 */
+    fn is_integer(&self) -> bool;
+
+    /** 加法。 */
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+
+    /** 减法。 */
+    fn checked_sub(&self, other: &Self) -> Option<Self>;
+
+    /** 乘法。 */
+    fn checked_mul(&self, other: &Self) -> Option<Self>;
+
+    /** 取负。 */
+    fn checked_neg(&self) -> Option<Self>;
+
+    /** 除法，不能精确表示时按选项中的精度和舍入方式处理。 */
+    fn checked_div(&self, other: &Self, options: &EvalOptions) -> Option<Self>;
+
+    /** 整除，结果是整数，`mode` 是商的取整方式。 */
+    fn checked_int_div(&self, other: &Self, mode: DivisionMode) -> Option<Self>;
+
+    /** 整数次乘方，负指数取倒数。 */
+    fn checked_powi(&self, exponent: i64, options: &EvalOptions) -> Option<Self>;
+
+    /** 取余，余数 = 被除数 - 除数 * 商。 */
+    fn checked_rem(&self, other: &Self, mode: DivisionMode) -> Option<Self> {
+        let quotient = self.checked_int_div(other, mode)?;
+        return self.checked_sub(&other.checked_mul(&quotient)?);
+    }
+}
+
+impl Numeric for BigDecimal {
+    const NAME: &'static str = "BigDecimal";
+
+    fn from_decimal(n: &BigDecimal) -> Option<BigDecimal> {
+        return Some(n.clone());
+    }
+
+    fn to_decimal(&self, _: i64, _: RoundingMode) -> BigDecimal {
+        return self.clone();
+    }
+
+    fn format(&self, options: &EvalOptions) -> String {
+        return options.format_number(self);
+    }
+
+    fn is_zero(&self) -> bool {
+        return Zero::is_zero(self);
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        if !self.is_integer() {
+            return None;
+        }
+        return ToPrimitive::to_i64(self);
+    }
+
+    fn is_integer(&self) -> bool {
+        return BigDecimal::is_integer(self);
+    }
+
+    fn checked_add(&self, other: &BigDecimal) -> Option<BigDecimal> {
+        return Some(self + other);
+    }
+
+    fn checked_sub(&self, other: &BigDecimal) -> Option<BigDecimal> {
+        return Some(self - other);
+    }
+
+    fn checked_mul(&self, other: &BigDecimal) -> Option<BigDecimal> {
+        return Some(self * other);
+    }
+
+    fn checked_neg(&self) -> Option<BigDecimal> {
+        return Some(-self);
+    }
+
+    fn checked_div(&self, other: &BigDecimal, options: &EvalOptions) -> Option<BigDecimal> {
+        // 按选项中的精度和舍入方式做除法，去掉结果末尾多余的 0
+        let quotient = options
+            .rounding
+            .divide(self, other, options.division_scale as i64);
+        return Some(quotient.normalized());
+    }
+
+    fn checked_int_div(&self, other: &BigDecimal, mode: DivisionMode) -> Option<BigDecimal> {
+        // 先把两个数对齐到相同的小数位数，再作为整数相除
+        let (_, left_scale) = self.as_bigint_and_exponent();
+        let (_, right_scale) = other.as_bigint_and_exponent();
+        let scale = left_scale.max(right_scale);
+
+        let (left_digits, _) = self.with_scale(scale).into_bigint_and_exponent();
+        let (right_digits, _) = other.with_scale(scale).into_bigint_and_exponent();
+
+        // BigInt 的除法本身就是向 0 取整
+        let mut quotient = &left_digits / &right_digits;
+
+        if mode == DivisionMode::Floored
+            && !(&left_digits % &right_digits).is_zero()
+            && left_digits.is_negative() != right_digits.is_negative()
+        {
+            // 除不尽且异号时，向 0 取整比向负无穷取整大 1
+            quotient -= 1;
+        }
+
+        return Some(BigDecimal::new(quotient, 0));
+    }
+
+    fn checked_powi(&self, exponent: i64, options: &EvalOptions) -> Option<BigDecimal> {
+        // 底数的整数部分和小数位数，整数次乘方只需要分别乘方
+        let (digits, scale) = self.as_bigint_and_exponent();
+        let abs_n = exponent.unsigned_abs();
+        let result = BigDecimal::new(digits.pow(abs_n as u32), scale * abs_n as i64);
+
+        if exponent < 0 {
+            return BigDecimal::one().checked_div(&result, options);
+        }
+        return Some(result);
+    }
+}
+
+impl Numeric for BigRational {
+    const NAME: &'static str = "BigRational";
+
+    fn from_decimal(n: &BigDecimal) -> Option<BigRational> {
+        let (digits, scale) = n.as_bigint_and_exponent();
+        if scale >= 0 {
+            return Some(BigRational::new(digits, ten_to_the(scale)?));
+        }
+        return Some(BigRational::from_integer(digits * ten_to_the(-scale)?));
+    }
+
+    fn to_decimal(&self, scale: i64, rounding: RoundingMode) -> BigDecimal {
+        let numer = BigDecimal::new(self.numer().clone(), 0);
+        let denom = BigDecimal::new(self.denom().clone(), 0);
+        return rounding.divide(&numer, &denom, scale).normalized();
+    }
+
+    fn format(&self, options: &EvalOptions) -> String {
+        let decimal = self
+            .to_decimal(options.output_scale as i64, options.rounding)
+            .to_string();
+        if self.is_integer() {
+            return decimal;
+        }
+
+        // 不是整数时在小数后面附上分数
+        let fraction = match options.fraction {
+            FractionStyle::Off => return decimal,
+            FractionStyle::Improper => format!("{}/{}", self.numer(), self.denom()),
+            FractionStyle::Mixed => {
+                // 带分数的整数部分向 0 取整，分数部分和整数部分同号
+                let whole = self.trunc();
+                let part = (self - &whole).abs();
+                if Zero::is_zero(&whole) {
+                    format!("{}/{}", self.numer(), self.denom())
+                } else {
+                    format!("{} {}/{}", whole.numer(), part.numer(), part.denom())
+                }
+            }
+        };
+        return format!("{} ({})", decimal, fraction);
+    }
+
+    fn is_zero(&self) -> bool {
+        return Zero::is_zero(self);
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        if !self.is_integer() {
+            return None;
+        }
+        return self.numer().to_i64();
+    }
+
+    fn is_integer(&self) -> bool {
+        return BigRational::is_integer(self);
+    }
+
+    fn checked_add(&self, other: &BigRational) -> Option<BigRational> {
+        return Some(self + other);
+    }
+
+    fn checked_sub(&self, other: &BigRational) -> Option<BigRational> {
+        return Some(self - other);
+    }
+
+    fn checked_mul(&self, other: &BigRational) -> Option<BigRational> {
+        return Some(self * other);
+    }
+
+    fn checked_neg(&self) -> Option<BigRational> {
+        return Some(-self);
+    }
+
+    fn checked_div(&self, other: &BigRational, _: &EvalOptions) -> Option<BigRational> {
+        // 有理数的除法是精确的
+        return Some(self / other);
+    }
+
+    fn checked_int_div(&self, other: &BigRational, mode: DivisionMode) -> Option<BigRational> {
+        let quotient = self / other;
+        return match mode {
+            DivisionMode::Floored => Some(quotient.floor()),
+            DivisionMode::Truncated => Some(quotient.trunc()),
+        };
+    }
+
+    fn checked_powi(&self, exponent: i64, _: &EvalOptions) -> Option<BigRational> {
+        // 负指数时 pow 会自动取倒数
+        return Some(self.pow(exponent as i32));
+    }
+}
+
+impl Numeric for f64 {
+    const NAME: &'static str = "f64";
+
+    fn from_decimal(n: &BigDecimal) -> Option<f64> {
+        // 通过字符串转换，得到最接近的浮点数
+        return finite(n.to_string().parse().ok()?);
+    }
+
+    fn to_decimal(&self, scale: i64, rounding: RoundingMode) -> BigDecimal {
+        // 科学记数法输出的是能还原出这个浮点数的最短的十进制数
+        let n = BigDecimal::from_str(&format!("{:e}", self)).unwrap();
+        return rounding.round(&n, scale).normalized();
+    }
+
+    fn format(&self, options: &EvalOptions) -> String {
+        return self
+            .to_decimal(options.output_scale as i64, options.rounding)
+            .to_string();
+    }
+
+    fn is_zero(&self) -> bool {
+        return *self == 0.0;
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        if !self.is_integer() || self.abs() > i64::MAX as f64 {
+            return None;
+        }
+        return Some(*self as i64);
+    }
+
+    fn is_integer(&self) -> bool {
+        return self.fract() == 0.0;
+    }
+
+    fn checked_add(&self, other: &f64) -> Option<f64> {
+        return finite(self + other);
+    }
+
+    fn checked_sub(&self, other: &f64) -> Option<f64> {
+        return finite(self - other);
+    }
+
+    fn checked_mul(&self, other: &f64) -> Option<f64> {
+        return finite(self * other);
+    }
+
+    fn checked_neg(&self) -> Option<f64> {
+        return Some(-self);
+    }
+
+    fn checked_div(&self, other: &f64, _: &EvalOptions) -> Option<f64> {
+        return finite(self / other);
+    }
+
+    fn checked_int_div(&self, other: &f64, mode: DivisionMode) -> Option<f64> {
+        let quotient = self / other;
+        return match mode {
+            DivisionMode::Floored => finite(quotient.floor()),
+            DivisionMode::Truncated => finite(quotient.trunc()),
+        };
+    }
+
+    fn checked_powi(&self, exponent: i64, _: &EvalOptions) -> Option<f64> {
+        // 指数的绝对值不超过 MAX_EXPONENT ，不会超出 i32 的范围
+        return finite(self.powi(exponent as i32));
+    }
+}
+
+impl Numeric for i128 {
+    const NAME: &'static str = "i128";
+
+    fn from_decimal(n: &BigDecimal) -> Option<i128> {
+        if !n.is_integer() {
+            return None;
+        }
+        let (digits, _) = n.with_scale(0).into_bigint_and_exponent();
+        return digits.to_i128();
+    }
+
+    fn to_decimal(&self, _: i64, _: RoundingMode) -> BigDecimal {
+        return BigDecimal::new(BigInt::from(*self), 0);
+    }
+
+    fn format(&self, _: &EvalOptions) -> String {
+        return self.to_string();
+    }
+
+    fn is_zero(&self) -> bool {
+        return *self == 0;
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        return i64::try_from(*self).ok();
+    }
+
+    fn is_integer(&self) -> bool {
+        return true;
+    }
+
+    fn checked_add(&self, other: &i128) -> Option<i128> {
+        return i128::checked_add(*self, *other);
+    }
+
+    fn checked_sub(&self, other: &i128) -> Option<i128> {
+        return i128::checked_sub(*self, *other);
+    }
+
+    fn checked_mul(&self, other: &i128) -> Option<i128> {
+        return i128::checked_mul(*self, *other);
+    }
+
+    fn checked_neg(&self) -> Option<i128> {
+        return i128::checked_neg(*self);
+    }
+
+    fn checked_div(&self, other: &i128, options: &EvalOptions) -> Option<i128> {
+        // 整数的除法按选项中的舍入方式舍入到整数
+        let quotient = options.rounding.divide(
+            &self.to_decimal(0, options.rounding),
+            &other.to_decimal(0, options.rounding),
+            0,
+        );
+        return i128::from_decimal(&quotient);
+    }
+
+    fn checked_int_div(&self, other: &i128, mode: DivisionMode) -> Option<i128> {
+        // i128 的除法向 0 取整，i128::MIN / -1 会溢出
+        let quotient = i128::checked_div(*self, *other)?;
+        if mode == DivisionMode::Floored && self % other != 0 && (*self < 0) != (*other < 0) {
+            // 除不尽且异号时，向 0 取整比向负无穷取整大 1
+            return Some(quotient - 1);
+        }
+        return Some(quotient);
+    }
+
+    fn checked_powi(&self, exponent: i64, options: &EvalOptions) -> Option<i128> {
+        if exponent >= 0 {
+            return i128::checked_pow(*self, exponent as u32);
+        }
+        // 负指数时结果的绝对值不超过 1 ，按除法舍入到整数
+        let divisor = BigInt::from(*self).pow(exponent.unsigned_abs() as u32);
+        let quotient = options
+            .rounding
+            .divide(&BigDecimal::one(), &BigDecimal::new(divisor, 0), 0);
+        return i128::from_decimal(&quotient);
+    }
+}
+
+/// 浮点数的运算结果是无穷大或者 NaN 时返回 `None`
+fn finite(n: f64) -> Option<f64> {
+    if n.is_finite() {
+        return Some(n);
+    }
+    return None;
+}
+
+#[cfg(test)]
+mod test {
+    use super::Numeric;
+    use crate::calculate::DivisionMode;
+    use crate::options::{EvalOptions, FractionStyle, RoundingMode};
+    use bigdecimal::num_bigint::BigInt;
+    use bigdecimal::BigDecimal;
+    use num_rational::BigRational;
+    use std::str::FromStr;
+
+    #[test]
+    fn rational_format_test() {
+        // 测试有理数的输出，依次为不显示分数、假分数、带分数
+        let input_vec = vec![
+            (
+                "7",
+                "3",
+                [
+                    "2.333333333333333",
+                    "2.333333333333333 (7/3)",
+                    "2.333333333333333 (2 1/3)",
+                ],
+            ),
+            (
+                "-7",
+                "3",
+                [
+                    "-2.333333333333333",
+                    "-2.333333333333333 (-7/3)",
+                    "-2.333333333333333 (-2 1/3)",
+                ],
+            ),
+            ("1", "4", ["0.25", "0.25 (1/4)", "0.25 (1/4)"]),
+            ("6", "3", ["2", "2", "2"]),
+        ];
+        for (numer, denom, expected) in input_vec {
+            let n = BigRational::new(numer.parse().unwrap(), denom.parse().unwrap());
+            let styles = [
+                FractionStyle::Off,
+                FractionStyle::Improper,
+                FractionStyle::Mixed,
+            ];
+            for (fraction, expected) in styles.iter().zip(expected) {
+                let options = EvalOptions {
+                    fraction: *fraction,
+                    ..EvalOptions::default()
+                };
+                assert_eq!(n.format(&options), expected, "input: {}/{}", numer, denom);
+            }
+        }
+    }
+
+    #[test]
+    fn checked_test() {
+        // 测试 i128 和 f64 超出范围或者不能表示时返回 None，和 i128 自带的方法同名时需要指明 trait
+        let options = EvalOptions::default();
+        let decimal = |s: &str| BigDecimal::from_str(s).unwrap();
+
+        assert_eq!(i128::from_decimal(&decimal("1.5")), None);
+        assert_eq!(i128::from_decimal(&decimal("1.50e1")), Some(15));
+        assert_eq!(i128::from_decimal(&decimal("1e40")), None);
+        assert_eq!(Numeric::checked_add(&i128::MAX, &1), None);
+        assert_eq!(i128::MIN.checked_int_div(&-1, DivisionMode::Floored), None);
+        assert_eq!(
+            (-7i128).checked_int_div(&2, DivisionMode::Floored),
+            Some(-4)
+        );
+        assert_eq!(2i128.checked_powi(127, &options), None);
+        assert_eq!(2i128.checked_powi(-1, &options), Some(0));
+
+        assert_eq!(f64::from_decimal(&decimal("1e400")), None);
+        assert_eq!(1e308f64.checked_mul(&10.0), None);
+        assert_eq!(10f64.checked_powi(400, &options), None);
+
+        // 指数超出 u32 的范围时不能转换为有理数，不会截断成错误的值
+        let scale = i64::from(u32::MAX) + 1;
+        let tiny = BigDecimal::new(BigInt::from(1), scale);
+        let huge = BigDecimal::new(BigInt::from(1), -scale);
+        assert_eq!(BigRational::from_decimal(&tiny), None);
+        assert_eq!(BigRational::from_decimal(&huge), None);
+    }
+
+    #[test]
+    fn format_test() {
+        // 测试 i128 和 f64 的输出，除法按选项中的舍入方式处理
+        let input_vec = vec![
+            (RoundingMode::Truncate, "3", "0.333333333333333"),
+            (RoundingMode::HalfUp, "4", "0.333333333333333"),
+            (RoundingMode::Floor, "3", "0.333333333333333"),
+        ];
+        for (rounding, int_expected, float_expected) in input_vec {
+            let options = EvalOptions {
+                rounding,
+                ..EvalOptions::default()
+            };
+            let int = Numeric::checked_div(&7i128, &2, &options).unwrap();
+            assert_eq!(int.format(&options), int_expected, "rounding: {}", rounding);
+            let float = 1f64.checked_div(&3.0, &options).unwrap();
+            assert_eq!(
+                float.format(&options),
+                float_expected,
+                "rounding: {}",
+                rounding
+            );
+        }
+        // 浮点数按最短的十进制形式输出
+        let sum = 0.1f64.checked_add(&0.2).unwrap();
+        assert_eq!(sum.format(&EvalOptions::default()), "0.3");
+    }
+}
@@ -0,0 +1,353 @@
+use crate::ast::{Expr, ExprKind, Stmt, StmtKind};
+use crate::calculate::{calculate_with_options, Env};
+use crate::lower::{lower, parenthesize};
+use crate::numeric::Numeric;
+use crate::options::EvalOptions;
+use crate::parse::Operator;
+use crate::token::Span;
+use bigdecimal::{BigDecimal, One, Zero};
+use std::fmt;
+
+type Num = BigDecimal;
+
+/**
+化简时使用的规则。
+
+* `RewriteRule::ConstantFold` 算出只有常量的子表达式，如 `2*3` 化简为 `6`
+* `RewriteRule::MultiplyByOne` 乘以 1 ，如 `x*1` 和 `1*x` 化简为 `x`
+* `RewriteRule::AddZero` 加 0 ，如 `x+0` 和 `0+x` 化简为 `x`
+* `RewriteRule::SubtractZero` 减 0 ，如 `x-0` 化简为 `x`
+* `RewriteRule::DoubleNegation` 两次取负，如 `-(-x)` 化简为 `x`
+
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RewriteRule {
+    /// 常量折叠
+    ConstantFold,
+    /// 乘以 1
+    MultiplyByOne,
+    /// 加 0
+    AddZero,
+    /// 减 0
+    SubtractZero,
+    /// 两次取负
+    DoubleNegation,
+}
+
+/**
+一次化简。
+
+* `rule` 使用的规则
+* `span` 被化简的子表达式在输入串中的区间
+* `before` 化简前的子表达式，完全加括号的中缀式
+* `after` 化简后的子表达式，完全加括号的中缀式
+
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rewrite {
+    /// 使用的规则
+    pub rule: RewriteRule,
+    /// 被化简的子表达式在输入串中的区间
+    pub span: Span,
+    /// 化简前的子表达式
+    pub before: String,
+    /// 化简后的子表达式
+    pub after: String,
+}
+
+impl fmt::Display for RewriteRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RewriteRule::ConstantFold => write!(f, "常量折叠"),
+            RewriteRule::MultiplyByOne => write!(f, "乘以 1"),
+            RewriteRule::AddZero => write!(f, "加 0"),
+            RewriteRule::SubtractZero => write!(f, "减 0"),
+            RewriteRule::DoubleNegation => write!(f, "两次取负"),
+        }
+    }
+}
+
+impl fmt::Display for Rewrite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} => {}", self.rule, self.before, self.after)
+    }
+}
+
+/**
+化简表达式，返回化简后的表达式和按发生顺序排列的每一次化简。
+
+从叶子向上化简：先去掉乘以 1 、加减 0 和两次取负，再算出只有常量的子表达式。
+常量按数字类型 `N` 的语义计算，算出的值以十进制数保存在语法树中，十进制数不能精确表示时不折叠，
+如有理数的 `1/3` 。计算出错的子表达式不折叠，如 `1/0` ，计算时仍然在原来的位置报错。
+
+* `expr` 需要化简的表达式
+* `options` 折叠常量时使用的计算选项
+ */
+pub fn optimize<N: Numeric>(expr: &Expr, options: &EvalOptions) -> (Expr, Vec<Rewrite>) {
+    let mut rewrites = vec![];
+    let output = optimize_into::<N>(expr, options, &mut rewrites);
+    return (output, rewrites);
+}
+
+/**
+化简每条语句中的表达式，返回化简后的语句和所有语句中的每一次化简。
+
+* `program` 语法分析得到的语句
+* `options` 折叠常量时使用的计算选项
+ */
+pub fn optimize_program<N: Numeric>(
+    program: &[Stmt],
+    options: &EvalOptions,
+) -> (Vec<Stmt>, Vec<Rewrite>) {
+    let mut rewrites = vec![];
+    let mut output = vec![];
+    for stmt in program {
+        let mut stmt = stmt.clone();
+        match &mut stmt.kind {
+            StmtKind::Let { value, .. } => {
+                *value = optimize_into::<N>(value, options, &mut rewrites)
+            }
+            StmtKind::Assign { value, .. } => {
+                *value = optimize_into::<N>(value, options, &mut rewrites)
+            }
+            StmtKind::Expr(value) => *value = optimize_into::<N>(value, options, &mut rewrites),
+        }
+        output.push(stmt);
+    }
+    return (output, rewrites);
+}
+
+fn optimize_into<N: Numeric>(
+    expr: &Expr,
+    options: &EvalOptions,
+    rewrites: &mut Vec<Rewrite>,
+) -> Expr {
+    // 先化简子表达式
+    let expr = match &expr.kind {
+        ExprKind::Number(_) | ExprKind::Variable(_) => return expr.clone(),
+        ExprKind::Unary {
+            op,
+            op_span,
+            operand,
+        } => {
+            let operand = optimize_into::<N>(operand, options, rewrites);
+            Expr::unary(*op, *op_span, operand)
+        }
+        ExprKind::Binary {
+            op,
+            op_span,
+            left,
+            right,
+        } => {
+            let left = optimize_into::<N>(left, options, rewrites);
+            let right = optimize_into::<N>(right, options, rewrites);
+            Expr::binary(*op, *op_span, left, right)
+        }
+        ExprKind::Call {
+            name,
+            name_span,
+            args,
+        } => {
+            let args = args
+                .iter()
+                .map(|arg| optimize_into::<N>(arg, options, rewrites))
+                .collect();
+            Expr::call(name.clone(), *name_span, args, expr.span)
+        }
+    };
+
+    if let Some((rule, output)) = simplify(&expr) {
+        rewrites.push(Rewrite {
+            rule,
+            span: expr.span,
+            before: parenthesize(&expr),
+            after: parenthesize(&output),
+        });
+        return output;
+    }
+
+    if let Some(value) = fold::<N>(&expr, options) {
+        let output = Expr::number(value, expr.span);
+        rewrites.push(Rewrite {
+            rule: RewriteRule::ConstantFold,
+            span: expr.span,
+            before: parenthesize(&expr),
+            after: parenthesize(&output),
+        });
+        return output;
+    }
+
+    return expr;
+}
+
+/// 去掉不改变结果的运算，返回使用的规则和剩下的操作数
+fn simplify(expr: &Expr) -> Option<(RewriteRule, Expr)> {
+    match &expr.kind {
+        ExprKind::Unary {
+            op: Operator::Minus,
+            operand,
+            ..
+        } => {
+            if let ExprKind::Unary {
+                op: Operator::Minus,
+                operand,
+                ..
+            } = &operand.kind
+            {
+                return Some((RewriteRule::DoubleNegation, (**operand).clone()));
+            }
+        }
+        ExprKind::Binary {
+            op, left, right, ..
+        } => {
+            let left = left.as_ref();
+            let right = right.as_ref();
+            match op {
+                Operator::Mul if is_number(right, Num::one()) => {
+                    return Some((RewriteRule::MultiplyByOne, left.clone()))
+                }
+                Operator::Mul if is_number(left, Num::one()) => {
+                    return Some((RewriteRule::MultiplyByOne, right.clone()))
+                }
+                Operator::Add if is_number(right, Num::zero()) => {
+                    return Some((RewriteRule::AddZero, left.clone()))
+                }
+                Operator::Add if is_number(left, Num::zero()) => {
+                    return Some((RewriteRule::AddZero, right.clone()))
+                }
+                Operator::Sub if is_number(right, Num::zero()) => {
+                    return Some((RewriteRule::SubtractZero, left.clone()))
+                }
+                _ => {}
+            }
+        }
+        _ => {}
+    }
+    return None;
+}
+
+/// 是否为值等于 `n` 的数字
+fn is_number(expr: &Expr, n: Num) -> bool {
+    return matches!(&expr.kind, ExprKind::Number(value) if *value == n);
+}
+
+/// 所有操作数都是数字时按数字类型 `N` 算出它的值，出错或者十进制数不能精确表示时返回 `None`
+fn fold<N: Numeric>(expr: &Expr, options: &EvalOptions) -> Option<Num> {
+    let constant = match &expr.kind {
+        ExprKind::Number(_) | ExprKind::Variable(_) => false,
+        // 单个数字取负保留为 `2 @` ，和外面的运算一起折叠，两次取负已经由 `simplify` 去掉
+        ExprKind::Unary { .. } => false,
+        ExprKind::Binary { left, right, .. } => is_constant(left) && is_constant(right),
+        ExprKind::Call { args, .. } => args.iter().all(is_constant),
+    };
+    if !constant {
+        return None;
+    }
+
+    // 出错的子表达式留到计算时再报错
+    let value = calculate_with_options::<N>(&lower(expr), &Env::default(), options).ok()?;
+    let decimal = value.to_decimal(options.division_scale as i64, options.rounding);
+    if N::from_decimal(&decimal)? != value {
+        return None;
+    }
+    return Some(decimal);
+}
+
+/// 是否为数字或者取负的数字
+fn is_constant(expr: &Expr) -> bool {
+    return match &expr.kind {
+        ExprKind::Number(_) => true,
+        ExprKind::Unary {
+            op: Operator::Minus,
+            operand,
+            ..
+        } => matches!(operand.kind, ExprKind::Number(_)),
+        _ => false,
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::{optimize, RewriteRule};
+    use crate::calculate::{calculate_with_options, CalcError, Env};
+    use crate::lower::{lower, parenthesize};
+    use crate::options::EvalOptions;
+    use crate::parse::parse;
+    use crate::token::{tokenization, Span};
+    use crate::token_render::*;
+    use bigdecimal::BigDecimal;
+    use num_rational::BigRational;
+
+    #[test]
+    fn optimize_test() {
+        // 测试化简的结果和使用的规则
+        let input_vec = vec![
+            (
+                "x*1 + 0 + 2*3",
+                "(x + 6)",
+                vec![
+                    RewriteRule::MultiplyByOne,
+                    RewriteRule::AddZero,
+                    RewriteRule::ConstantFold,
+                ],
+            ),
+            (
+                "-(-x) - 0",
+                "x",
+                vec![RewriteRule::DoubleNegation, RewriteRule::SubtractZero],
+            ),
+            (
+                "1 * (2 + 3) * y",
+                "(5 * y)",
+                vec![RewriteRule::ConstantFold, RewriteRule::MultiplyByOne],
+            ),
+            ("sqrt(16) + (-2)", "2", vec![RewriteRule::ConstantFold; 2]),
+            ("x * (-2)", "(x * (@ 2))", vec![]),
+            ("-2 ^ 2 + x", "((@ 4) + x)", vec![RewriteRule::ConstantFold]),
+            ("x * (1 / 0)", "(x * (1 / 0))", vec![]),
+            ("2 ^ 0.5 + 1", "((2 ^ 0.5) + 1)", vec![]),
+        ];
+        let options = EvalOptions::default();
+        for (input, expected, rules) in input_vec {
+            let tokens = tokenization(input).unwrap();
+            let expr = parse(&mut TokenRender::new_with_tokens(tokens)).unwrap();
+            let (output, rewrites) = optimize::<BigDecimal>(&expr, &options);
+            assert_eq!(parenthesize(&output), expected, "input: {}", input);
+            let output_rules: Vec<RewriteRule> = rewrites.iter().map(|r| r.rule).collect();
+            assert_eq!(output_rules, rules, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn optimize_report_test() {
+        // 测试化简的记录
+        let tokens = tokenization("x*1 + 2*3").unwrap();
+        let expr = parse(&mut TokenRender::new_with_tokens(tokens)).unwrap();
+        let (_, rewrites) = optimize::<BigDecimal>(&expr, &EvalOptions::default());
+        let output: Vec<String> = rewrites.iter().map(|r| r.to_string()).collect();
+        assert_eq!(output, ["乘以 1: (x * 1) => x", "常量折叠: (2 * 3) => 6"]);
+        assert_eq!(rewrites[1].span, Span::new(6, 9));
+    }
+
+    #[test]
+    fn optimize_error_test() {
+        // 测试不折叠的除 0 在计算时仍然指出原来的运算符
+        let input = "1 + 2 / (3 - 3)";
+        let tokens = tokenization(input).unwrap();
+        let expr = parse(&mut TokenRender::new_with_tokens(tokens)).unwrap();
+        let options = EvalOptions::default();
+        let (output, _) = optimize::<BigDecimal>(&expr, &options);
+        assert_eq!(parenthesize(&output), "(1 + (2 / 0))");
+        let result = calculate_with_options(&lower(&output), &Env::new(), &options);
+        assert_eq!(result, Err(CalcError::DivisionByZero(Span::new(6, 7))));
+    }
+
+    #[test]
+    fn optimize_rational_test() {
+        // 测试十进制数不能精确表示的有理数不折叠
+        let tokens = tokenization("1/3 + 1/4").unwrap();
+        let expr = parse(&mut TokenRender::new_with_tokens(tokens)).unwrap();
+        let (output, _) = optimize::<BigRational>(&expr, &EvalOptions::default());
+        assert_eq!(parenthesize(&output), "((1 / 3) + 0.25)");
+    }
+}
@@ -0,0 +1,457 @@
+use crate::calculate::DivisionMode;
+use bigdecimal::num_bigint::BigInt;
+use bigdecimal::{BigDecimal, Signed, Zero};
+use std::fmt;
+use std::str::FromStr;
+
+type Num = BigDecimal;
+
+/**
+舍入方式。
+
+* `RoundingMode::HalfEven` 四舍六入五成双，如 `2.5 -> 2` ，`3.5 -> 4`
+* `RoundingMode::HalfUp` 四舍五入，五向远离 0 的方向进位，如 `2.5 -> 3` ，`-2.5 -> -3`
+* `RoundingMode::Floor` 向负无穷取整
+* `RoundingMode::Ceiling` 向正无穷取整
+* `RoundingMode::Truncate` 向 0 截断
+
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoundingMode {
+    /// 四舍六入五成双
+    HalfEven,
+    /// 四舍五入
+    HalfUp,
+    /// 向负无穷取整
+    Floor,
+    /// 向正无穷取整
+    Ceiling,
+    /// 向 0 截断
+    Truncate,
+}
+
+impl RoundingMode {
+    /** 所有的舍入方式，图形界面的下拉框按这个顺序排列。 */
+    pub const ALL: [RoundingMode; 5] = [
+        RoundingMode::Truncate,
+        RoundingMode::HalfUp,
+        RoundingMode::HalfEven,
+        RoundingMode::Floor,
+        RoundingMode::Ceiling,
+    ];
+
+    /**
+    把数字舍入到小数点后 `scale` 位，位数本来就不超过 `scale` 时原样返回。
+
+    数字的小数位数和 `scale` 相差超过 `u32::MAX` 时 panic 。
+     */
+    pub fn round(&self, n: &Num, scale: i64) -> Num {
+        let (digits, n_scale) = n.as_bigint_and_exponent();
+        if n_scale <= scale {
+            return n.clone();
+        }
+        let quotient = self.round_quotient(&digits, &scale_power(n_scale - scale));
+        return BigDecimal::new(quotient, scale);
+    }
+
+    /**
+    定点除法，结果舍入到小数点后 `scale` 位。
+
+    需要移动的小数位数超过 `u32::MAX` 时 panic 。
+
+    * `left` 被除数
+    * `right` 除数，不能为 0
+    * `scale` 结果保留的小数位数
+     */
+    pub fn divide(&self, left: &Num, right: &Num, scale: i64) -> Num {
+        let (left_digits, left_scale) = left.as_bigint_and_exponent();
+        let (right_digits, right_scale) = right.as_bigint_and_exponent();
+
+        // left / right = left_digits / right_digits * 10^(right_scale - left_scale)
+        let shift = scale + right_scale - left_scale;
+        let quotient = if shift >= 0 {
+            self.round_quotient(&(left_digits * scale_power(shift)), &right_digits)
+        } else {
+            self.round_quotient(&left_digits, &(right_digits * scale_power(-shift)))
+        };
+
+        return BigDecimal::new(quotient, scale);
+    }
+
+    /// 把分数 `numerator / denominator` 舍入为整数
+    fn round_quotient(&self, numerator: &BigInt, denominator: &BigInt) -> BigInt {
+        // BigInt 的除法向 0 取整，余数和被除数同号
+        let quotient = numerator / denominator;
+        let remainder = numerator % denominator;
+        if remainder.is_zero() {
+            return quotient;
+        }
+
+        let negative = numerator.is_negative() != denominator.is_negative();
+        // 远离 0 的方向进一位
+        let away = if negative {
+            &quotient - 1
+        } else {
+            &quotient + 1
+        };
+        // 比较余数和除数的一半
+        let twice_remainder: BigInt = remainder.abs() * 2;
+        let half = twice_remainder.cmp(&denominator.abs());
+
+        match self {
+            RoundingMode::Truncate => quotient,
+            RoundingMode::Floor if negative => away,
+            RoundingMode::Floor => quotient,
+            RoundingMode::Ceiling if negative => quotient,
+            RoundingMode::Ceiling => away,
+            RoundingMode::HalfUp if half.is_lt() => quotient,
+            RoundingMode::HalfUp => away,
+            RoundingMode::HalfEven if half.is_lt() => quotient,
+            RoundingMode::HalfEven if half.is_gt() => away,
+            RoundingMode::HalfEven if (&quotient % BigInt::from(2)).is_zero() => quotient,
+            RoundingMode::HalfEven => away,
+        }
+    }
+}
+
+impl fmt::Display for RoundingMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RoundingMode::HalfEven => write!(f, "half-even"),
+            RoundingMode::HalfUp => write!(f, "half-up"),
+            RoundingMode::Floor => write!(f, "floor"),
+            RoundingMode::Ceiling => write!(f, "ceiling"),
+            RoundingMode::Truncate => write!(f, "truncate"),
+        }
+    }
+}
+
+impl FromStr for RoundingMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<RoundingMode, String> {
+        return RoundingMode::ALL
+            .iter()
+            .find(|mode| mode.to_string() == s)
+            .copied()
+            .ok_or_else(|| {
+                format!(
+                    "未知的舍入方式 {}，可用的有 half-even 、half-up 、floor 、ceiling 、truncate",
+                    s
+                )
+            });
+    }
+}
+
+/**
+精确的有理数运算时，结果不是整数时附带的分数形式。
+
+* `FractionStyle::Off` 只输出小数
+* `FractionStyle::Improper` 附带假分数，如 `7/3`
+* `FractionStyle::Mixed` 附带带分数，如 `2 1/3`
+
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FractionStyle {
+    /// 只输出小数
+    Off,
+    /// 附带假分数
+    Improper,
+    /// 附带带分数
+    Mixed,
+}
+
+impl FractionStyle {
+    /** 所有的分数形式，图形界面的下拉框按这个顺序排列。 */
+    pub const ALL: [FractionStyle; 3] = [
+        FractionStyle::Improper,
+        FractionStyle::Mixed,
+        FractionStyle::Off,
+    ];
+}
+
+impl fmt::Display for FractionStyle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FractionStyle::Off => write!(f, "off"),
+            FractionStyle::Improper => write!(f, "improper"),
+            FractionStyle::Mixed => write!(f, "mixed"),
+        }
+    }
+}
+
+impl FromStr for FractionStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<FractionStyle, String> {
+        return FractionStyle::ALL
+            .iter()
+            .find(|style| style.to_string() == s)
+            .copied()
+            .ok_or_else(|| format!("未知的分数形式 {}，可用的有 improper 、mixed 、off", s));
+    }
+}
+
+/**
+计算使用的数字类型，命令行和图形界面按它选择 [`crate::Numeric`] 的实现。
+
+* `Backend::Decimal` 十进制数 `BigDecimal` ，除法按选项中的精度舍入
+* `Backend::Rational` 有理数 `BigRational` ，运算都是精确的
+* `Backend::Float` 浮点数 `f64` ，速度快但是有浮点误差
+* `Backend::Integer` 整数 `i128` ，溢出时报错
+
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// 十进制数
+    Decimal,
+    /// 有理数
+    Rational,
+    /// 浮点数
+    Float,
+    /// 整数
+    Integer,
+}
+
+impl Backend {
+    /** 所有的数字类型，图形界面的下拉框按这个顺序排列。 */
+    pub const ALL: [Backend; 4] = [
+        Backend::Decimal,
+        Backend::Rational,
+        Backend::Float,
+        Backend::Integer,
+    ];
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Backend::Decimal => write!(f, "decimal"),
+            Backend::Rational => write!(f, "rational"),
+            Backend::Float => write!(f, "float"),
+            Backend::Integer => write!(f, "integer"),
+        }
+    }
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Backend, String> {
+        return Backend::ALL
+            .iter()
+            .find(|backend| backend.to_string() == s)
+            .copied()
+            .ok_or_else(|| {
+                format!(
+                    "未知的数字类型 {}，可用的有 decimal 、rational 、float 、integer",
+                    s
+                )
+            });
+    }
+}
+
+/**
+表达式的输出形式，命令行和图形界面按它选择显示的表达式或者中间代码。
+
+* `Notation::Postfix` 逆波兰式，也就是后缀表达式
+* `Notation::Prefix` 波兰式，也就是前缀表达式
+* `Notation::Infix` 完全加括号的中缀式
+* `Notation::Quadruple` 四元式
+* `Notation::Triple` 三元式
+* `Notation::Dag` 消除公共子表达式后的逆波兰式和四元式
+
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Notation {
+    /// 逆波兰式
+    Postfix,
+    /// 波兰式
+    Prefix,
+    /// 完全加括号的中缀式
+    Infix,
+    /// 四元式
+    Quadruple,
+    /// 三元式
+    Triple,
+    /// 消除公共子表达式后的逆波兰式和四元式
+    Dag,
+}
+
+impl Notation {
+    /** 所有的输出形式，图形界面的下拉框按这个顺序排列。 */
+    pub const ALL: [Notation; 6] = [
+        Notation::Postfix,
+        Notation::Prefix,
+        Notation::Infix,
+        Notation::Quadruple,
+        Notation::Triple,
+        Notation::Dag,
+    ];
+}
+
+impl fmt::Display for Notation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Notation::Postfix => write!(f, "postfix"),
+            Notation::Prefix => write!(f, "prefix"),
+            Notation::Infix => write!(f, "infix"),
+            Notation::Quadruple => write!(f, "quadruple"),
+            Notation::Triple => write!(f, "triple"),
+            Notation::Dag => write!(f, "dag"),
+        }
+    }
+}
+
+impl FromStr for Notation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Notation, String> {
+        return Notation::ALL
+            .iter()
+            .find(|notation| notation.to_string() == s)
+            .copied()
+            .ok_or_else(|| {
+                format!(
+                    "未知的输出形式 {}，可用的有 postfix 、prefix 、infix 、quadruple 、triple 、dag",
+                    s
+                )
+            });
+    }
+}
+
+/// 除法精度和输出位数的上限，和图形界面中的最大值相同，避免算出巨大的 10 的幂卡死程序
+pub const MAX_SCALE: u64 = 1000;
+
+/**
+计算和输出的选项。
+
+* `division_scale` 除法和函数的结果保留的小数位数
+* `output_scale` 输出的数字保留的小数位数
+* `rounding` 除法和输出时的舍入方式
+* `division_mode` 整除和取余对负数的处理方式
+* `fraction` 有理数运算时附带的分数形式
+* `trace` 是否记录每条语句的计算过程
+* `optimize` 是否在计算前化简表达式
+
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EvalOptions {
+    /// 除法和函数的结果保留的小数位数，默认 64 位
+    pub division_scale: u64,
+    /// 输出的数字保留的小数位数，默认 15 位
+    pub output_scale: u64,
+    /// 除法和输出时的舍入方式，默认向 0 截断
+    pub rounding: RoundingMode,
+    /// 整除和取余对负数的处理方式，默认商向负无穷取整
+    pub division_mode: DivisionMode,
+    /// 有理数运算时附带的分数形式，默认附带假分数
+    pub fraction: FractionStyle,
+    /// 是否记录每条语句的计算过程，默认不记录
+    pub trace: bool,
+    /// 是否在计算前折叠常量并去掉乘以 1 、加减 0 这样的运算，默认不化简
+    pub optimize: bool,
+}
+
+impl Default for EvalOptions {
+    fn default() -> EvalOptions {
+        return EvalOptions {
+            division_scale: 64,
+            output_scale: 15,
+            rounding: RoundingMode::Truncate,
+            division_mode: DivisionMode::Floored,
+            fraction: FractionStyle::Improper,
+            trace: false,
+            optimize: false,
+        };
+    }
+}
+
+impl EvalOptions {
+    /** 按输出的位数和舍入方式格式化数字。 */
+    pub fn format_number(&self, n: &Num) -> String {
+        return self
+            .rounding
+            .round(n, self.output_scale as i64)
+            .normalized()
+            .to_string();
+    }
+}
+
+/// 10 的 `n` 次方，`n` 为负数或者超出 `u32` 的范围时返回 `None`
+pub(crate) fn ten_to_the(n: i64) -> Option<BigInt> {
+    let n = u32::try_from(n).ok()?;
+    return Some(BigInt::from(10).pow(n));
+}
+
+/// 舍入和除法移动小数点用的 10 的 `n` 次方，超出范围时 panic ，而不是算出错误的结果
+fn scale_power(n: i64) -> BigInt {
+    return ten_to_the(n).unwrap_or_else(|| panic!("需要移动 {} 位小数，超出了 u32 的范围", n));
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ten_to_the, RoundingMode};
+    use bigdecimal::num_bigint::BigInt;
+    use bigdecimal::BigDecimal;
+    use std::str::FromStr;
+
+    #[test]
+    fn round_test() {
+        // 测试每种舍入方式，依次为 Truncate HalfUp HalfEven Floor Ceiling
+        let input_vec = vec![
+            ("2.5", 0, ["2", "3", "2", "2", "3"]),
+            ("3.5", 0, ["3", "4", "4", "3", "4"]),
+            ("-2.5", 0, ["-2", "-3", "-2", "-3", "-2"]),
+            ("2.51", 0, ["2", "3", "3", "2", "3"]),
+            ("-0.125", 2, ["-0.12", "-0.13", "-0.12", "-0.13", "-0.12"]),
+            ("1.2", 3, ["1.2", "1.2", "1.2", "1.2", "1.2"]),
+            ("1250", -2, ["1200", "1300", "1200", "1200", "1300"]),
+        ];
+        for (input, scale, expected) in input_vec {
+            let n = BigDecimal::from_str(input).unwrap();
+            for (mode, expected) in RoundingMode::ALL.iter().zip(expected) {
+                assert_eq!(
+                    mode.round(&n, scale).normalized().to_string(),
+                    expected,
+                    "input: {}, mode: {}",
+                    input,
+                    mode
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn ten_to_the_test() {
+        // 测试超出 u32 的范围时不会截断成错误的次数
+        assert_eq!(ten_to_the(3), Some(BigInt::from(1000)));
+        assert_eq!(ten_to_the(-1), None);
+        assert_eq!(ten_to_the(i64::from(u32::MAX) + 1), None);
+    }
+
+    #[test]
+    fn divide_test() {
+        // 测试除法结果的舍入
+        let input_vec = vec![
+            ("2", "3", 3, ["0.666", "0.667", "0.667", "0.666", "0.667"]),
+            ("-1", "8", 2, ["-0.12", "-0.13", "-0.12", "-0.13", "-0.12"]),
+            ("1", "-0.04", 0, ["-25", "-25", "-25", "-25", "-25"]),
+            ("5", "2", 0, ["2", "3", "2", "2", "3"]),
+        ];
+        for (left, right, scale, expected) in input_vec {
+            let left = BigDecimal::from_str(left).unwrap();
+            let right = BigDecimal::from_str(right).unwrap();
+            for (mode, expected) in RoundingMode::ALL.iter().zip(expected) {
+                assert_eq!(
+                    mode.divide(&left, &right, scale).normalized().to_string(),
+                    expected,
+                    "left: {}, right: {}, mode: {}",
+                    left,
+                    right,
+                    mode
+                );
+            }
+        }
+    }
+}
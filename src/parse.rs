@@ -1,3 +1,5 @@
+use crate::ast::{Expr, Stmt, StmtKind};
+use crate::parse_trace::ParseTrace;
 use crate::token::*;
 use crate::token_render::*;
 use bigdecimal::BigDecimal;
@@ -38,14 +40,57 @@ pub enum ParseErrType {
 /**
 Reverse Polish notation RPN 逆波兰式元素。
 
+每个元素都带有它在输入串中对应的区间，计算出错时可以据此指出出错的位置。
+
 * `Operator` 运算符
 * `Number` 数字
+* `Variable` 变量
+* `Call` 函数调用
  */
+#[derive(Debug, Clone)]
 pub enum RPNItem {
     /// 运算符
-    Operator(Operator),
+    Operator {
+        /// 具体的运算符
+        op: Operator,
+        /// 运算符在输入串中的区间
+        span: Span,
+    },
     /// 数字
-    Number(Num),
+    Number {
+        /// 数字的值
+        value: Num,
+        /// 数字在输入串中的区间
+        span: Span,
+    },
+    /// 变量，计算时从环境中取值
+    Variable {
+        /// 变量名
+        name: String,
+        /// 变量在输入串中的区间
+        span: Span,
+    },
+    /// 函数调用，从运算栈中取出 `arity` 个参数
+    Call {
+        /// 函数名
+        name: String,
+        /// 参数个数
+        arity: usize,
+        /// 函数调用在输入串中的区间，从函数名开始到右括号结束
+        span: Span,
+    },
+}
+
+impl RPNItem {
+    /** 元素在输入串中的区间，取负运算符对应输入中的负号。 */
+    pub fn span(&self) -> Span {
+        match self {
+            RPNItem::Operator { span, .. } => *span,
+            RPNItem::Number { span, .. } => *span,
+            RPNItem::Variable { span, .. } => *span,
+            RPNItem::Call { span, .. } => *span,
+        }
+    }
 }
 
 /**
@@ -55,8 +100,12 @@ pub enum RPNItem {
 * `Operator::Sub` 减运算符
 * `Operator::Mul` 乘运算符
 * `Operator::Div` 除运算符
+* `Operator::Pow` 乘方运算符
+* `Operator::Mod` 取余运算符
+* `Operator::IntDiv` 整除运算符
 * `Operator::Minus` 取负运算符
 */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Operator {
     /// 加运算符
     Add,
@@ -66,6 +115,12 @@ pub enum Operator {
     Mul,
     /// 除运算符
     Div,
+    /// 乘方运算符
+    Pow,
+    /// 取余运算符
+    Mod,
+    /// 整除运算符
+    IntDiv,
     /// 取负运算符
     Minus,
 }
@@ -77,6 +132,9 @@ impl fmt::Display for Operator {
             Operator::Sub => write!(f, "-"),
             Operator::Mul => write!(f, "*"),
             Operator::Div => write!(f, "/"),
+            Operator::Pow => write!(f, "^"),
+            Operator::Mod => write!(f, "%"),
+            Operator::IntDiv => write!(f, "//"),
             Operator::Minus => write!(f, "@"),
         }
     }
@@ -85,51 +143,288 @@ impl fmt::Display for Operator {
 impl fmt::Display for RPNItem {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            RPNItem::Operator(op) => write!(f, "{}", op),
-            RPNItem::Number(n) => {
-                write!(f, "{}", n.normalized().to_string())
+            RPNItem::Operator { op, .. } => write!(f, "{}", op),
+            RPNItem::Number { value, .. } => {
+                write!(f, "{}", value.normalized().to_string())
             }
+            RPNItem::Variable { name, .. } => write!(f, "{}", name),
+            RPNItem::Call { name, arity, .. } => write!(f, "{}:{}", name, arity),
         }
     }
 }
 
 // 大致上的文法和函数的对应关系，将就着看吧
+// parse_program -> [ s ] { sep [ s ] } #
+// sep -> ; | 换行
+// s -> let ident = a | ident = a | a
 // parse_sentence -> a#
 // a -> m1 { o1 m }
 // o1 -> + | -
 // m1 -> at1 { o2 at }
-// o2 -> * | /
-// at1 -> -(a) | (a) | -num | num
+// o2 -> * | / | % | //
+// at1 -> -p | p
 // m -> at { o2 at }
-// at -> (a) | num
+// at -> p
+// p -> f [ o3 p ]
+// o3 -> ^ | **
+// f -> (a) | num | ident [ call ]
+// call -> ( [ a { , a } ] )
+// 开启跟踪时记录的非终结符名字和上面的文法一致，at1 和 m1 是 is_first 为 true 的 at 和 m ，
+// 另外 num 和 ident 分别对应数字和标识符，call 的部分直接记在 f 中
+
+/// 解析非终结符 `name` ，开启了跟踪时记录进入和离开，`success` 判断解析结果是否成功
+fn traced<T>(
+    render: &mut TokenRender,
+    name: &'static str,
+    rule: impl FnOnce(&mut TokenRender) -> T,
+    success: fn(&T) -> bool,
+) -> T {
+    render.enter(name);
+    let result = rule(render);
+    render.exit(success(&result));
+    return result;
+}
+
+/// 语法分析部分，解析由分号或换行分隔的多条语句，空语句会被跳过，返回第一个错误
+pub fn parse_program(render: &mut TokenRender) -> Result<Vec<Stmt>, ParseErr> {
+    return parse_program_recovering(render).map_err(|mut errors| errors.remove(0));
+}
+
+/**
+语法分析部分，解析由分号或换行分隔的多条语句，出错后继续解析，返回所有错误。
 
-/// 语法分析部分
-pub fn parse(render: &mut TokenRender) -> Result<Vec<RPNItem>, ParseErr> {
+表达式中出错时跳到下一个 `)` 或运算符继续解析，语句中出错时跳到下一条语句继续解析。
+ */
+pub fn parse_program_recovering(render: &mut TokenRender) -> Result<Vec<Stmt>, Vec<ParseErr>> {
     let mut output = vec![];
 
-    a(render, &mut output)?;
+    loop {
+        // 跳过空语句
+        while sep(render) {}
 
-    if render.is_empty() {
-        // 如果全部 token 都解析完了
+        if render.is_empty() {
+            // 如果全部 token 都解析完了
+            break;
+        }
+
+        match s(render) {
+            Ok(stmt) => output.push(stmt),
+            Err(e) => render.report(e),
+        }
+
+        if !render.is_empty() && !sep(render) {
+            // 语句后面既不是结束也不是分隔符
+            let token = render.peek().clone();
+
+            render.report(ParseErr {
+                reason: format!("期望获得 ; 或换行，却得到了{}", token.info).to_owned(),
+                err_type: ParseErrType::Unexpected(token),
+            });
+
+            // 跳过这条语句剩下的部分
+            synchronize(render, is_separator);
+        }
+    }
 
+    let errors = render.take_errors();
+    if errors.is_empty() {
         return Ok(output);
     } else {
+        return Err(errors);
+    }
+}
+
+/// 跳过 token 直到遇到 `is_stop` 为真的 token 或者结尾，不消费这个 token
+fn synchronize(render: &mut TokenRender, is_stop: fn(&TokenInfo) -> bool) {
+    while !render.is_empty() && !is_stop(&render.peek().info) {
+        render.next();
+    }
+}
+
+/// 是不是语句分隔符
+fn is_separator(info: &TokenInfo) -> bool {
+    return matches!(
+        info,
+        TokenInfo::Symbol(SymbolType::Semicolon) | TokenInfo::Symbol(SymbolType::Newline)
+    );
+}
+
+/// 表达式出错后可以继续解析的位置：右括号、逗号、运算符和语句分隔符
+fn is_sync(info: &TokenInfo) -> bool {
+    return is_separator(info)
+        || matches!(
+            info,
+            TokenInfo::Symbol(SymbolType::RightBracket)
+                | TokenInfo::Symbol(SymbolType::Comma)
+                | TokenInfo::Symbol(SymbolType::Add)
+                | TokenInfo::Symbol(SymbolType::Sub)
+                | TokenInfo::Symbol(SymbolType::Mul)
+                | TokenInfo::Symbol(SymbolType::Div)
+                | TokenInfo::Symbol(SymbolType::Mod)
+                | TokenInfo::Symbol(SymbolType::IntDiv)
+                | TokenInfo::Symbol(SymbolType::Pow)
+        );
+}
+
+/**
+记下表达式中的错误并跳到下一个可以继续解析的位置。
+
+返回一个占位的数字节点让解析继续下去，有错误时 AST 不会被返回，所以占位节点不会被使用。
+ */
+fn recover(render: &mut TokenRender, err: ParseErr) -> Expr {
+    render.report(err);
+    synchronize(render, is_sync);
+    return Expr::number(Num::zero(), Span::new(0, 0));
+}
+
+/// 解析 render 中 next 是不是 ';' / 换行，如果是就消费掉
+fn sep(render: &mut TokenRender) -> bool {
+    return render.try_token(TokenInfo::Symbol(SymbolType::Semicolon))
+        || render.try_token(TokenInfo::Symbol(SymbolType::Newline));
+}
+
+fn s(render: &mut TokenRender) -> Result<Stmt, ParseErr> {
+    return traced(render, "s", s_rule, Result::is_ok);
+}
+
+fn s_rule(render: &mut TokenRender) -> Result<Stmt, ParseErr> {
+    let start = render.peek().span();
+
+    if render.try_token(TokenInfo::Symbol(SymbolType::Let)) {
+        // 定义变量
+        let name_token = render.expect(
+            TokenInfo::Identifier(String::new()),
+            |token| {
+                return format!("let 后面期望获得变量名，却得到了{}", token.info).to_owned();
+            },
+            "let 后面期望获得变量名，却意外终止".to_owned(),
+        )?;
+        render.next(); // 消费变量名
+
+        let (name, name_span, value) = assignment(render, name_token)?;
+        let span = start.to(value.span);
+
+        return Ok(Stmt {
+            kind: StmtKind::Let {
+                name,
+                name_span,
+                value,
+            },
+            span,
+        });
+    }
+
+    let is_assign = match (render.peek_at(0), render.peek_at(1)) {
+        (Some(first), Some(second)) => {
+            matches!(first.info, TokenInfo::Identifier(_))
+                && matches!(second.info, TokenInfo::Symbol(SymbolType::Assign))
+        }
+        _ => false,
+    };
+
+    if is_assign {
+        // 给变量赋值
+        let name_token = render.peek().clone();
+        render.next(); // 消费变量名
+
+        let (name, name_span, value) = assignment(render, name_token)?;
+        let span = start.to(value.span);
+
+        return Ok(Stmt {
+            kind: StmtKind::Assign {
+                name,
+                name_span,
+                value,
+            },
+            span,
+        });
+    }
+
+    // 单独的表达式
+    let value = a(render)?;
+    let span = value.span;
+
+    return Ok(Stmt {
+        kind: StmtKind::Expr(value),
+        span,
+    });
+}
+
+/// 解析变量名之后的 `= a` 部分，返回变量名、变量名的区间和值
+fn assignment(
+    render: &mut TokenRender,
+    name_token: Token,
+) -> Result<(String, Span, Expr), ParseErr> {
+    render.expect(
+        TokenInfo::Symbol(SymbolType::Assign),
+        |token| {
+            return format!("变量名后面期望获得 = ，却得到了{}", token.info).to_owned();
+        },
+        "变量名后面期望获得 = ，却意外终止".to_owned(),
+    )?;
+    render.next(); // 消费等号
+
+    let value = a(render)?;
+
+    let name_span = name_token.span();
+    match name_token.info {
+        TokenInfo::Identifier(name) => return Ok((name, name_span, value)),
+        _ => unreachable!(),
+    }
+}
+
+/**
+语法分析部分，只解析单个表达式，同时记录语法分析的过程。
+
+返回语法分析的结果和跟踪记录，出错时跟踪记录到出错的位置为止。
+ */
+pub fn parse_traced(render: &mut TokenRender) -> (Result<Expr, ParseErr>, ParseTrace) {
+    render.enable_trace();
+    let expr = parse(render);
+    return (expr, render.take_trace().unwrap());
+}
+
+/// 语法分析部分，只解析单个表达式，返回 AST 的根节点
+pub fn parse(render: &mut TokenRender) -> Result<Expr, ParseErr> {
+    let expr = a(render);
+
+    if let Err(e) = &expr {
+        render.report(e.clone());
+    }
+
+    if !render.is_empty() {
         // 如果还有剩余的 token
-        let token = render.peek();
+        let token = render.peek().clone();
 
-        return Err(ParseErr {
+        render.report(ParseErr {
             reason: format!("期望输入结束，却得到了{}", token.info).to_owned(),
-            err_type: ParseErrType::Unexpected(render.peek().clone()),
+            err_type: ParseErrType::Unexpected(token),
         });
     }
+
+    let mut errors = render.take_errors();
+    if errors.is_empty() {
+        // 如果全部 token 都解析完了
+
+        return expr;
+    } else {
+        return Err(errors.remove(0));
+    }
+}
+
+fn a(render: &mut TokenRender) -> Result<Expr, ParseErr> {
+    return traced(render, "a", a_rule, Result::is_ok);
 }
 
-fn a(render: &mut TokenRender, output: &mut Vec<RPNItem>) -> Result<(), ParseErr> {
+fn a_rule(render: &mut TokenRender) -> Result<Expr, ParseErr> {
     // 交给 m 解析，is_first 为 true
-    m(render, output, true)?;
+    let mut left = m(render, true)?;
 
     // 如果还有未解析完的 token ，就试试接着解析
     while !render.is_empty() {
+        // 记下运算符的位置
+        let op_span = render.peek().span();
+
         // 检查 next 是不是 '+' / '-'
         let op = o1(render);
 
@@ -138,17 +433,21 @@ fn a(render: &mut TokenRender, output: &mut Vec<RPNItem>) -> Result<(), ParseErr
         }
 
         // 交给 m 解析，is_first 为 false
-        m(render, output, false)?;
+        let right = m(render, false)?;
 
-        // 把之前得到的操作符 push 到输出。
-        output.push(RPNItem::Operator(op.unwrap()));
+        // 左结合，把之前的结果作为左操作数
+        left = Expr::binary(op.unwrap(), op_span, left, right);
     }
 
-    return Ok(());
+    return Ok(left);
 }
 
 /// 解析 render 中 next 是不是 '+' / '-' ，如果是就返回相应的运算符
 fn o1(render: &mut TokenRender) -> Result<Operator, ()> {
+    return traced(render, "o1", o1_rule, Result::is_ok);
+}
+
+fn o1_rule(render: &mut TokenRender) -> Result<Operator, ()> {
     if render.try_token(TokenInfo::Symbol(SymbolType::Add)) {
         return Ok(Operator::Add);
     }
@@ -158,13 +457,26 @@ fn o1(render: &mut TokenRender) -> Result<Operator, ()> {
     return Err(());
 }
 
-fn m(render: &mut TokenRender, output: &mut Vec<RPNItem>, is_first: bool) -> Result<(), ParseErr> {
+fn m(render: &mut TokenRender, is_first: bool) -> Result<Expr, ParseErr> {
+    let name = if is_first { "m1" } else { "m" };
+    return traced(
+        render,
+        name,
+        |render| m_rule(render, is_first),
+        Result::is_ok,
+    );
+}
+
+fn m_rule(render: &mut TokenRender, is_first: bool) -> Result<Expr, ParseErr> {
     // 交给 at 解析，is_first 为 true
-    at(render, output, is_first)?;
+    let mut left = at(render, is_first)?;
 
     // 如果还有未解析完的 token ，就试试接着解析
     while !render.is_empty() {
-        // 检查 next 是不是 '*' / '/'
+        // 记下运算符的位置
+        let op_span = render.peek().span();
+
+        // 检查 next 是不是 '*' / '/' / '%' / '//'
         let op = o2(render);
 
         if op.is_err() {
@@ -173,69 +485,228 @@ fn m(render: &mut TokenRender, output: &mut Vec<RPNItem>, is_first: bool) -> Res
         }
 
         // 交给 at 解析，is_first 为 false
-        at(render, output, false)?;
+        let right = at(render, false)?;
 
-        // 把之前得到的操作符 push 到输出。
-        output.push(RPNItem::Operator(op.unwrap()));
+        // 左结合，把之前的结果作为左操作数
+        left = Expr::binary(op.unwrap(), op_span, left, right);
     }
 
-    return Ok(());
+    return Ok(left);
 }
 
-/// 解析 render 中 next 是不是 '*' / '/' ，如果是就返回相应的运算符
+/// 解析 render 中 next 是不是 '*' / '/' / '%' / '//' ，如果是就返回相应的运算符
 fn o2(render: &mut TokenRender) -> Result<Operator, ()> {
+    return traced(render, "o2", o2_rule, Result::is_ok);
+}
+
+fn o2_rule(render: &mut TokenRender) -> Result<Operator, ()> {
     if render.try_token(TokenInfo::Symbol(SymbolType::Mul)) {
         return Ok(Operator::Mul);
     }
     if render.try_token(TokenInfo::Symbol(SymbolType::Div)) {
         return Ok(Operator::Div);
     }
+    if render.try_token(TokenInfo::Symbol(SymbolType::Mod)) {
+        return Ok(Operator::Mod);
+    }
+    if render.try_token(TokenInfo::Symbol(SymbolType::IntDiv)) {
+        return Ok(Operator::IntDiv);
+    }
     return Err(());
 }
 
-fn at(render: &mut TokenRender, output: &mut Vec<RPNItem>, is_first: bool) -> Result<(), ParseErr> {
-    let mut is_neg = false;
+fn at(render: &mut TokenRender, is_first: bool) -> Result<Expr, ParseErr> {
+    let name = if is_first { "at1" } else { "at" };
+    return traced(
+        render,
+        name,
+        |render| at_rule(render, is_first),
+        Result::is_ok,
+    );
+}
+
+fn at_rule(render: &mut TokenRender, is_first: bool) -> Result<Expr, ParseErr> {
+    let mut neg_span = None;
 
-    if is_first && render.try_token(TokenInfo::Symbol(SymbolType::Sub)) {
-        // 当前为第一个子表达式且以减号开头时解析减号为单目运算符负号
-        is_neg = true;
+    if is_first && !render.is_empty() {
+        let span = render.peek().span();
+        if render.try_token(TokenInfo::Symbol(SymbolType::Sub)) {
+            // 当前为第一个子表达式且以减号开头时解析减号为单目运算符负号
+            neg_span = Some(span);
+        }
     }
 
-    if !num(render, output) {
-        // 解析左括号
-        render.expect(
-            TokenInfo::Symbol(SymbolType::LeftBracket),
-            |token| {
-                return format!("期望获得 ( 或数字，却得到了{}", token.info).to_owned();
-            },
-            "期望获得 ( 或数字，却意外终止".to_owned(),
-        )?;
-        render.next(); // 消费左括号
+    // 乘方的优先级比负号高，所以 -2^2 是 -(2^2)
+    let expr = p(render)?;
+
+    if let Some(span) = neg_span {
+        // 如果解析出负号就用取负运算符包住这个子表达式
+        return Ok(Expr::unary(Operator::Minus, span, expr));
+    }
+
+    return Ok(expr);
+}
+
+fn p(render: &mut TokenRender) -> Result<Expr, ParseErr> {
+    return traced(render, "p", p_rule, Result::is_ok);
+}
+
+fn p_rule(render: &mut TokenRender) -> Result<Expr, ParseErr> {
+    let base = f(render)?;
+
+    if !render.is_empty() {
+        // 记下运算符的位置
+        let op_span = render.peek().span();
+
+        // 检查 next 是不是 '^' / '**'
+        if let Ok(op) = o3(render) {
+            // 右结合，递归解析指数部分
+            let exponent = p(render)?;
+
+            return Ok(Expr::binary(op, op_span, base, exponent));
+        }
+    }
+
+    return Ok(base);
+}
+
+/// 解析 render 中 next 是不是 '^' / '**' ，如果是就返回乘方运算符
+fn o3(render: &mut TokenRender) -> Result<Operator, ()> {
+    return traced(render, "o3", o3_rule, Result::is_ok);
+}
+
+fn o3_rule(render: &mut TokenRender) -> Result<Operator, ()> {
+    if render.try_token(TokenInfo::Symbol(SymbolType::Pow)) {
+        return Ok(Operator::Pow);
+    }
+    return Err(());
+}
+
+fn f(render: &mut TokenRender) -> Result<Expr, ParseErr> {
+    return traced(render, "f", f_rule, Result::is_ok);
+}
+
+fn f_rule(render: &mut TokenRender) -> Result<Expr, ParseErr> {
+    if let Some(expr) = num(render) {
+        return Ok(expr);
+    }
 
-        // 递归调用 a
-        a(render, output)?;
+    if let Some(expr) = ident_or_call(render)? {
+        return Ok(expr);
+    }
+
+    // 解析左括号
+    let left_bracket = match render.expect(
+        TokenInfo::Symbol(SymbolType::LeftBracket),
+        |token| {
+            return format!("期望获得 ( 、数字或标识符，却得到了{}", token.info).to_owned();
+        },
+        "期望获得 ( 、数字或标识符，却意外终止".to_owned(),
+    ) {
+        Ok(token) => token,
+        Err(e) => return Ok(recover(render, e)),
+    };
+    render.next(); // 消费左括号
+
+    // 递归调用 a
+    let mut inner = a(render)?;
+
+    // 解析右括号
+    match render.expect(
+        TokenInfo::Symbol(SymbolType::RightBracket),
+        |token| {
+            return format!("期望获得 )，却得到了{}", token.info).to_owned();
+        },
+        "期望获得 ) ，却意外终止".to_owned(),
+    ) {
+        Ok(right_bracket) => {
+            render.next(); // 消费右括号
 
-        // 解析右括号
-        render.expect(
+            // 括号表达式的区间包含两边的括号
+            inner.span = left_bracket.span().to(right_bracket.span());
+        }
+        Err(e) => {
+            // 缺少右括号，记下错误后假装它存在
+            render.report(e);
+            inner.span = left_bracket.span().to(inner.span);
+        }
+    }
+
+    return Ok(inner);
+}
+
+/// 解析 next 是不是变量或函数调用，标识符后面跟着左括号的是函数调用，不是标识符就返回 `None`
+fn ident_or_call(render: &mut TokenRender) -> Result<Option<Expr>, ParseErr> {
+    let name_token = match ident(render) {
+        Some(token) => token,
+        None => return Ok(None),
+    };
+
+    let name = match &name_token.info {
+        TokenInfo::Identifier(name) => name.clone(),
+        _ => unreachable!(),
+    };
+
+    // 检查是不是函数调用
+    if !render.try_token(TokenInfo::Symbol(SymbolType::LeftBracket)) {
+        // 不是函数调用就是变量
+        return Ok(Some(Expr::variable(name, name_token.span())));
+    }
+
+    let mut args = vec![];
+
+    if !render.try_token(TokenInfo::Symbol(SymbolType::RightBracket)) {
+        // 有参数，参数之间用逗号分隔
+        args.push(a(render)?);
+        while render.try_token(TokenInfo::Symbol(SymbolType::Comma)) {
+            args.push(a(render)?);
+        }
+
+        // 解析右括号，缺少右括号时记下错误后假装它存在
+        match render.expect(
             TokenInfo::Symbol(SymbolType::RightBracket),
             |token| {
-                return format!("期望获得 )，却得到了{}", token.info).to_owned();
+                return format!("期望获得 , 或 )，却得到了{}", token.info).to_owned();
             },
-            "期望获得 ) ，却意外终止".to_owned(),
-        )?;
-        render.next(); // 消费右括号
+            "期望获得 , 或 ) ，却意外终止".to_owned(),
+        ) {
+            Ok(_) => render.next(), // 消费右括号
+            Err(e) => render.report(e),
+        }
     }
 
-    if is_neg {
-        // 如果解析出符号就往输出中 push 一个取负运算符
-        output.push(RPNItem::Operator(Operator::Minus));
+    // 函数调用的区间到右括号为止，右括号就是上一个被消费的 token
+    let span = name_token.span().to(render.last().span());
+
+    return Ok(Some(Expr::call(name, name_token.span(), args, span)));
+}
+
+// 解析 next 是不是标识符，是的话消费掉并返回这个 token
+fn ident(render: &mut TokenRender) -> Option<Token> {
+    return traced(render, "ident", ident_rule, Option::is_some);
+}
+
+fn ident_rule(render: &mut TokenRender) -> Option<Token> {
+    if let Ok(temp) = render.expect(
+        TokenInfo::Identifier(String::new()),
+        |_| {
+            return "".to_owned();
+        },
+        "".to_owned(),
+    ) {
+        render.next();
+        return Some(temp);
     }
 
-    return Ok(());
+    return None;
 }
 
 // 解析 next 是不是数字
-fn num(render: &mut TokenRender, output: &mut Vec<RPNItem>) -> bool {
+fn num(render: &mut TokenRender) -> Option<Expr> {
+    return traced(render, "num", num_rule, Option::is_some);
+}
+
+fn num_rule(render: &mut TokenRender) -> Option<Expr> {
     // 检查是不是数字类型
     // 错了就错了，没有人关心这里的错误信息，只要有错误就可以
     if let Ok(temp) = render.expect(
@@ -245,24 +716,23 @@ fn num(render: &mut TokenRender, output: &mut Vec<RPNItem>) -> bool {
         },
         "".to_owned(),
     ) {
+        let span = temp.span();
         if let TokenInfo::Number(n) = temp.info {
             // 取出具体的数字信息
             // 消费掉这个 token
             render.next();
-            // 往输出中 push 这个数字
-            output.push(RPNItem::Number(n));
-
-            return true;
+            // 返回这个数字节点
+            return Some(Expr::number(n, span));
         }
     }
 
-    return false;
+    return None;
 }
 
 #[cfg(test)]
 mod test {
     use super::tokenization;
-    use crate::parse::parse;
+    use crate::parse::{parse, parse_program, parse_program_recovering};
     use crate::token_render::*;
 
     #[test]
@@ -287,6 +757,24 @@ mod test {
             "-3+.5",
             "(-2)",
             "-(2)",
+            "3**3",
+            "2^3^2",
+            "-2^2",
+            "2^(-1)",
+            "(2+1)^2*3",
+            "7%3",
+            "-7//2*3",
+            "7.5%(-2)",
+            "sqrt(2)",
+            "max(1, 2, 3)",
+            "-abs(-2)",
+            "min(1, max(2, 3))^2",
+            "round(2.5)+1",
+            "pi()",
+            "x",
+            "rate * (1 + x)",
+            "-x^2",
+            "sqrt(x) + max(a, b)",
         ];
         for i in 0..input_vec.len() {
             let re = tokenization(input_vec[i]);
@@ -300,8 +788,41 @@ mod test {
     fn parse_test_fail() {
         // 测试不符合语法的内容
         let input_vec = vec![
-            "56+", "1e9-", "*1.0", "(", ")", "()", "(((2)", "3**3", "4-*2", "45(+6)", "4 5", "++",
-            "--15", "-(+5)", "++++++1", "+1-", "+3", "3++2", "3--2",
+            "56+",
+            "1e9-",
+            "*1.0",
+            "(",
+            ")",
+            "()",
+            "(((2)",
+            "3***3",
+            "4-*2",
+            "45(+6)",
+            "4 5",
+            "++",
+            "--15",
+            "-(+5)",
+            "++++++1",
+            "+1-",
+            "+3",
+            "3++2",
+            "3--2",
+            "2^",
+            "^2",
+            "2^-1",
+            "2^^3",
+            "7///2",
+            "7%%2",
+            "%3",
+            "sqrt(",
+            "max(1,)",
+            "max(,1)",
+            "sqrt 2",
+            "sqrt(2)(3)",
+            "(1, 2)",
+            "1, 2",
+            "x y",
+            "x(",
         ];
         for i in 0..input_vec.len() {
             let re = tokenization(input_vec[i]);
@@ -310,4 +831,78 @@ mod test {
             assert!(re.is_err(), "input: {}", input_vec[i]);
         }
     }
+
+    #[test]
+    fn parse_program_test_success() {
+        // 测试符合语法的多条语句，以及解析出来的语句数量
+        let input_vec = vec![
+            ("", 0),
+            ("1", 1),
+            ("let x = 1; x + 1", 2),
+            ("x = 2\ny = x", 2),
+            ("1;;2;", 2),
+            ("\n\nlet rate = 0.5\n\n", 1),
+            ("let x = -2^2\nx = x * (1 + x); sqrt(x)", 3),
+        ];
+        for (input, count) in input_vec {
+            let re = tokenization(input);
+            assert!(re.is_ok());
+            let re = parse_program(&mut TokenRender::new_with_tokens(re.unwrap()));
+            assert!(re.is_ok(), "input: {}", input);
+            assert_eq!(re.unwrap().len(), count, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn parse_program_test_fail() {
+        // 测试不符合语法的多条语句
+        let input_vec = vec![
+            "let = 1",
+            "let x 1",
+            "let x = ",
+            "let 1 = 2",
+            "let let = 2",
+            "x = = 1",
+            "1 2",
+            "3 = 4",
+            "x = 1 y = 2",
+            "1;2 3",
+        ];
+        for input in input_vec {
+            let re = tokenization(input);
+            assert!(re.is_ok());
+            let re = parse_program(&mut TokenRender::new_with_tokens(re.unwrap()));
+            assert!(re.is_err(), "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn parse_program_test_recovering() {
+        // 测试出错后继续解析，收集所有错误的位置
+        let input_vec = vec![
+            ("1 + * 2", vec![4]),
+            ("(1 + ); 2 +", vec![5, 11]),
+            ("1 + * 2; 3 ** / 4\n(5", vec![4, 14, 20]),
+            ("max(1 2) + 3", vec![6]),
+            ("let = 1; let x 2; 3 4", vec![4, 15, 20]),
+            ("1 + 2", vec![]),
+        ];
+        for (input, positions) in input_vec {
+            let re = tokenization(input);
+            assert!(re.is_ok());
+            let re = parse_program_recovering(&mut TokenRender::new_with_tokens(re.unwrap()));
+            let errors = match re {
+                Ok(_) => vec![],
+                Err(errors) => errors,
+            };
+            let error_positions = errors
+                .iter()
+                .map(|e| match &e.err_type {
+                    super::ParseErrType::Unexpected(token) => token.position,
+                    super::ParseErrType::Insufficient => input.len(),
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(error_positions, positions, "input: {}", input);
+        }
+    }
 }
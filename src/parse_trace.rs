@@ -0,0 +1,332 @@
+use crate::token::Span;
+
+/**
+语法分析过程中的一个事件。
+
+* `rule` 文法中的非终结符，如 `a` 、`m1` 、`num`
+* `depth` 递归下降的深度，最外层为 0
+* `token` 事件发生时的下一个 token 的原文本，已经到结尾时为 `None`
+* `kind` 进入还是离开这个非终结符
+
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceEvent {
+    /// 文法中的非终结符
+    pub rule: &'static str,
+    /// 递归下降的深度，最外层为 0
+    pub depth: usize,
+    /// 事件发生时的下一个 token 的原文本，已经到结尾时为 `None`
+    pub token: Option<String>,
+    /// 进入还是离开这个非终结符
+    pub kind: TraceEventKind,
+}
+
+/**
+语法分析事件的类型。
+
+* `TraceEventKind::Enter` 进入非终结符
+* `TraceEventKind::Success` 离开非终结符，成功解析
+* `TraceEventKind::Failure` 离开非终结符，没有匹配或者出错
+
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceEventKind {
+    /// 进入非终结符
+    Enter,
+    /// 成功解析
+    Success,
+    /// 没有匹配或者出错
+    Failure,
+}
+
+/**
+语法树中的一个节点，也就是成功解析的一个非终结符。
+
+* `rule` 非终结符
+* `children` 按顺序排列的子节点和这个非终结符直接消费的 token
+
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceNode {
+    /// 非终结符
+    pub rule: &'static str,
+    /// 按顺序排列的子节点和这个非终结符直接消费的 token
+    pub children: Vec<TraceChild>,
+}
+
+/**
+语法树节点的子节点。
+
+* `TraceChild::Rule` 非终结符
+* `TraceChild::Terminal` 终结符，也就是消费的 token
+
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum TraceChild {
+    /// 非终结符
+    Rule(TraceNode),
+    /// 终结符
+    Terminal {
+        /// token 的原文本
+        text: String,
+        /// token 在输入串中的区间
+        span: Span,
+    },
+}
+
+/**
+语法分析的跟踪记录。
+
+由 `TokenRender` 在语法分析时记录，包括每个非终结符的进入和离开，以及成功解析的非终结符构成的语法树，
+可以输出缩进的调用过程和最左推导。
+ */
+#[derive(Debug, Clone, Default)]
+pub struct ParseTrace {
+    events: Vec<TraceEvent>,
+    roots: Vec<TraceNode>,
+    stack: Vec<TraceNode>,
+}
+
+/// 句型中的符号
+enum Symbol<'a> {
+    Rule(&'a TraceNode),
+    Terminal(&'a str),
+}
+
+impl ParseTrace {
+    /** 初始化一个空的跟踪记录。 */
+    pub fn new() -> ParseTrace {
+        return ParseTrace::default();
+    }
+
+    /** 按发生顺序排列的所有事件。 */
+    pub fn events(&self) -> &[TraceEvent] {
+        return &self.events;
+    }
+
+    /** 最外层成功解析的非终结符，每条语句或者每个表达式一个。 */
+    pub fn roots(&self) -> &[TraceNode] {
+        return &self.roots;
+    }
+
+    /** 进入非终结符 `rule` ，`token` 是下一个 token 的原文本。 */
+    pub fn enter(&mut self, rule: &'static str, token: Option<String>) {
+        self.events.push(TraceEvent {
+            rule,
+            depth: self.stack.len(),
+            token,
+            kind: TraceEventKind::Enter,
+        });
+        self.stack.push(TraceNode {
+            rule,
+            children: vec![],
+        });
+    }
+
+    /** 离开最近进入的非终结符，失败的非终结符不会出现在语法树中。 */
+    pub fn exit(&mut self, success: bool, token: Option<String>) {
+        let node = self.stack.pop().expect("离开的非终结符没有对应的进入");
+        self.events.push(TraceEvent {
+            rule: node.rule,
+            depth: self.stack.len(),
+            token,
+            kind: if success {
+                TraceEventKind::Success
+            } else {
+                TraceEventKind::Failure
+            },
+        });
+
+        if !success {
+            return;
+        }
+        match self.stack.last_mut() {
+            Some(parent) => parent.children.push(TraceChild::Rule(node)),
+            None => self.roots.push(node),
+        }
+    }
+
+    /** 记下当前的非终结符消费了一个 token ，不在任何非终结符中时忽略。 */
+    pub fn terminal(&mut self, text: &str, span: Span) {
+        if let Some(node) = self.stack.last_mut() {
+            node.children.push(TraceChild::Terminal {
+                text: text.to_owned(),
+                span,
+            });
+        }
+    }
+
+    /**
+    缩进的调用过程，每个事件占一行，每深一层多缩进两个空格。
+
+    如 `进入 num ，下一个 token 为 1` 、`离开 num ，成功，下一个 token 为 +` 。
+     */
+    pub fn call_trace_text(&self) -> String {
+        let mut lines = vec![];
+        for event in &self.events {
+            let token = match &event.token {
+                Some(token) => format!("下一个 token 为 {}", token),
+                None => "已经到结尾".to_owned(),
+            };
+            let action = match event.kind {
+                TraceEventKind::Enter => format!("进入 {} ，{}", event.rule, token),
+                TraceEventKind::Success => format!("离开 {} ，成功，{}", event.rule, token),
+                TraceEventKind::Failure => format!("离开 {} ，失败，{}", event.rule, token),
+            };
+            lines.push(format!("{}{}", "  ".repeat(event.depth), action));
+        }
+        return lines.join("\n");
+    }
+
+    /**
+    每个最外层非终结符的最左推导，每一步是一个句型。
+
+    每一步把句型中最左边的非终结符替换为它在语法树中的子节点。
+     */
+    pub fn derivations(&self) -> Vec<Vec<String>> {
+        let mut output = vec![];
+        for root in &self.roots {
+            let mut forms = vec![];
+            let mut form = vec![Symbol::Rule(root)];
+            loop {
+                forms.push(form_text(&form));
+
+                // 找到最左边的非终结符
+                let index = match form.iter().position(|s| matches!(s, Symbol::Rule(_))) {
+                    Some(index) => index,
+                    None => break,
+                };
+                let node = match form[index] {
+                    Symbol::Rule(node) => node,
+                    Symbol::Terminal(_) => unreachable!(),
+                };
+
+                // 替换为它的子节点
+                let children = node.children.iter().map(|child| match child {
+                    TraceChild::Rule(node) => Symbol::Rule(node),
+                    TraceChild::Terminal { text, .. } => Symbol::Terminal(text),
+                });
+                form.splice(index..index + 1, children);
+            }
+            output.push(forms);
+        }
+        return output;
+    }
+
+    /** 最左推导的文本，第一行是开始的非终结符，之后每行以 `=>` 开头，不同的语句之间空一行。 */
+    pub fn derivation_text(&self) -> String {
+        return self
+            .derivations()
+            .iter()
+            .map(|forms| {
+                let mut lines = vec![forms[0].clone()];
+                for form in &forms[1..] {
+                    lines.push(format!("=> {}", form));
+                }
+                return lines.join("\n");
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n");
+    }
+}
+
+/// 句型的文本，符号之间用空格分隔
+fn form_text(form: &[Symbol]) -> String {
+    return form
+        .iter()
+        .map(|symbol| match symbol {
+            Symbol::Rule(node) => node.rule,
+            Symbol::Terminal(text) => text,
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parse::{parse_program, parse_traced};
+    use crate::token::tokenization;
+    use crate::token_render::*;
+
+    #[test]
+    fn derivation_test() {
+        // 测试最左推导的每一步
+        let input_vec = vec![
+            (
+                "1+2",
+                vec![
+                    "a", "m1 o1 m", "at1 o1 m", "p o1 m", "f o1 m", "num o1 m", "1 o1 m", "1 + m",
+                    "1 + at", "1 + p", "1 + f", "1 + num", "1 + 2",
+                ],
+            ),
+            (
+                "-x^2",
+                vec![
+                    "a",
+                    "m1",
+                    "at1",
+                    "- p",
+                    "- f o3 p",
+                    "- ident o3 p",
+                    "- x o3 p",
+                    "- x ^ p",
+                    "- x ^ f",
+                    "- x ^ num",
+                    "- x ^ 2",
+                ],
+            ),
+            (
+                "(1)",
+                vec![
+                    "a", "m1", "at1", "p", "f", "( a )", "( m1 )", "( at1 )", "( p )", "( f )",
+                    "( num )", "( 1 )",
+                ],
+            ),
+        ];
+        for (input, expected) in input_vec {
+            let tokens = tokenization(input).unwrap();
+            let (result, trace) = parse_traced(&mut TokenRender::new_with_tokens(tokens));
+            assert!(result.is_ok(), "input: {}", input);
+            assert_eq!(trace.derivations(), vec![expected], "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn call_trace_test() {
+        // 测试缩进的调用过程，没有匹配的非终结符也会记录下来
+        let tokens = tokenization("x").unwrap();
+        let (_, trace) = parse_traced(&mut TokenRender::new_with_tokens(tokens));
+        let expected = "\
+进入 a ，下一个 token 为 x
+  进入 m1 ，下一个 token 为 x
+    进入 at1 ，下一个 token 为 x
+      进入 p ，下一个 token 为 x
+        进入 f ，下一个 token 为 x
+          进入 num ，下一个 token 为 x
+          离开 num ，失败，下一个 token 为 x
+          进入 ident ，下一个 token 为 x
+          离开 ident ，成功，已经到结尾
+        离开 f ，成功，已经到结尾
+      离开 p ，成功，已经到结尾
+    离开 at1 ，成功，已经到结尾
+  离开 m1 ，成功，已经到结尾
+离开 a ，成功，已经到结尾";
+        assert_eq!(trace.call_trace_text(), expected);
+    }
+
+    #[test]
+    fn program_derivation_test() {
+        // 测试多条语句时每条语句各有一个推导
+        let tokens = tokenization("let y = 1; y").unwrap();
+        let mut render = TokenRender::new_with_tokens(tokens);
+        render.enable_trace();
+        assert!(parse_program(&mut render).is_ok());
+
+        let trace = render.take_trace().unwrap();
+        let derivations = trace.derivations();
+        assert_eq!(derivations.len(), 2);
+        assert_eq!(derivations[0][..2], ["s", "let y = a"]);
+        assert_eq!(derivations[0].last().unwrap(), "let y = 1");
+        assert_eq!(derivations[1].last().unwrap(), "y");
+    }
+}
@@ -0,0 +1,176 @@
+use crate::ast::{Expr, Stmt, StmtKind};
+use crate::calculate::{calculate_traced, calculate_with_options, CalcError, Env, EvalStep};
+use crate::lower::{lower, lower_prefix, parenthesize};
+use crate::numeric::Numeric;
+use crate::options::EvalOptions;
+use crate::parse::RPNItem;
+use bigdecimal::BigDecimal;
+
+type Num = BigDecimal;
+
+/**
+单条语句的执行结果。
+
+* `expr` 语句中的表达式，波兰式和完全加括号的中缀式需要时才从它生成
+* `rpn` 语句中表达式翻译出来的逆波兰式
+* `target` 被赋值的变量名，单独的表达式为 `None`
+* `value` 表达式的值或者计算错误
+* `trace` 表达式的计算过程，选项中不记录计算过程时为空
+
+ */
+pub struct StmtResult<N = Num> {
+    /// 语句中的表达式，波兰式和完全加括号的中缀式需要时才从它生成
+    pub expr: Expr,
+    /// 语句中表达式翻译出来的逆波兰式
+    pub rpn: Vec<RPNItem>,
+    /// 被赋值的变量名，单独的表达式为 `None`
+    pub target: Option<String>,
+    /// 表达式的值或者计算错误
+    pub value: Result<N, CalcError>,
+    /// 表达式的计算过程，选项中不记录计算过程时为空
+    pub trace: Vec<EvalStep<N>>,
+}
+
+impl<N> StmtResult<N> {
+    /** 语句中表达式翻译出来的波兰式。 */
+    pub fn prefix(&self) -> Vec<RPNItem> {
+        return lower_prefix(&self.expr);
+    }
+
+    /** 语句中表达式的完全加括号的中缀式。 */
+    pub fn infix(&self) -> String {
+        return parenthesize(&self.expr);
+    }
+}
+
+/**
+按顺序执行多条语句。
+
+`let` 语句定义变量，赋值语句只能给已经定义的变量赋值。遇到第一个计算错误时停止执行，
+返回的结果中最后一条就是出错的语句。
+
+* `program` 语法分析得到的语句
+* `env` 计算环境，执行完后保留所有定义的变量
+* `options` 计算的选项
+ */
+pub fn run_program<N: Numeric>(
+    program: &[Stmt],
+    env: &mut Env<N>,
+    options: &EvalOptions,
+) -> Vec<StmtResult<N>> {
+    let mut output = vec![];
+
+    for stmt in program {
+        let result = run_stmt(stmt, env, options);
+        let failed = result.value.is_err();
+        output.push(result);
+
+        if failed {
+            break;
+        }
+    }
+
+    return output;
+}
+
+fn run_stmt<N: Numeric>(stmt: &Stmt, env: &mut Env<N>, options: &EvalOptions) -> StmtResult<N> {
+    // 把语句中的表达式翻译为逆波兰式并计算
+    let expr = stmt.value().clone();
+    let rpn = lower(&expr);
+    let (value, trace) = if options.trace {
+        calculate_traced(&rpn, env, options)
+    } else {
+        (calculate_with_options(&rpn, env, options), vec![])
+    };
+
+    let target = match &stmt.kind {
+        StmtKind::Let { name, .. } => Some(name.clone()),
+        StmtKind::Assign {
+            name, name_span, ..
+        } => {
+            if env.get(name).is_none() {
+                // 赋值前变量必须已经定义
+                return StmtResult {
+                    expr,
+                    rpn,
+                    target: Some(name.clone()),
+                    value: Err(CalcError::UndefinedAssignment {
+                        name: name.clone(),
+                        span: *name_span,
+                    }),
+                    trace,
+                };
+            }
+            Some(name.clone())
+        }
+        StmtKind::Expr(_) => None,
+    };
+
+    if let (Some(name), Ok(n)) = (&target, &value) {
+        env.set(name, n.clone());
+    }
+
+    return StmtResult {
+        expr,
+        rpn,
+        target,
+        value,
+        trace,
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::run_program;
+    use crate::calculate::Env;
+    use crate::options::EvalOptions;
+    use crate::parse::parse_program;
+    use crate::token::tokenization;
+    use crate::token_render::*;
+
+    #[test]
+    fn run_program_test() {
+        // 测试每条语句的值
+        let input_vec = vec![
+            ("let x = 2; x = x * 3; x + 1", vec!["2", "6", "7"]),
+            ("let a = 1\nlet a = a + 1\na", vec!["1", "2", "2"]),
+            (
+                "let r = 0.5\nlet x = 4\nr * (1 + x)",
+                vec!["0.5", "4", "2.5"],
+            ),
+        ];
+        for (input, expected) in input_vec {
+            let tokens = tokenization(input).unwrap();
+            let program = parse_program(&mut TokenRender::new_with_tokens(tokens)).unwrap();
+            let results = run_program(&program, &mut Env::new(), &EvalOptions::default());
+            let values = results
+                .iter()
+                .map(|r| r.value.as_ref().unwrap().normalized().to_string())
+                .collect::<Vec<_>>();
+            assert_eq!(values, expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn run_program_test_fail() {
+        // 测试出错时停止执行，并指出出错的变量
+        let input_vec = vec![
+            ("let x = 1; y = 2; x", 11..12),
+            ("let x = 1\nx + y\nx", 14..15),
+            ("let x = x", 8..9),
+        ];
+        for (input, span) in input_vec {
+            let tokens = tokenization(input).unwrap();
+            let program = parse_program(&mut TokenRender::new_with_tokens(tokens)).unwrap();
+            let results = run_program(&program, &mut Env::new(), &EvalOptions::default());
+            let last = results.last().unwrap();
+            match &last.value {
+                Err(e) => match e.span() {
+                    Some(s) => assert_eq!(s.start..s.end, span, "input: {}", input),
+                    None => panic!("input: {}", input),
+                },
+                Ok(_) => panic!("input: {}", input),
+            }
+        }
+    }
+}
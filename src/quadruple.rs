@@ -0,0 +1,567 @@
+use crate::ast::{Expr, ExprKind, Stmt, StmtKind};
+use crate::calculate::{execute, CalcError, Env};
+use crate::numeric::Numeric;
+use crate::options::EvalOptions;
+use crate::parse::{Operator, RPNItem};
+use crate::token::Span;
+use bigdecimal::BigDecimal;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+type Num = BigDecimal;
+
+/**
+四元式和三元式的运算对象。
+
+* `Operand::Number` 数字
+* `Operand::Variable` 变量
+* `Operand::Temp` 临时变量 `t1` 、`t2` ……，只出现在四元式中
+* `Operand::Ref` 第几个三元式的结果，只出现在三元式中
+
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    /// 数字
+    Number {
+        /// 数字的值
+        value: Num,
+        /// 数字在输入串中的区间
+        span: Span,
+    },
+    /// 变量
+    Variable {
+        /// 变量名
+        name: String,
+        /// 变量在输入串中的区间
+        span: Span,
+    },
+    /// 临时变量，编号从 1 开始
+    Temp(usize),
+    /// 第几个三元式的结果，编号从 0 开始
+    Ref(usize),
+}
+
+/**
+四元式和三元式的运算。
+
+* `QuadOp::Operator` 运算符，取负仍然写作 `@`
+* `QuadOp::Param` 传递函数调用的一个参数
+* `QuadOp::Call` 用之前传递的 `arity` 个参数调用函数
+* `QuadOp::Let` 定义变量
+* `QuadOp::Assign` 给已经定义的变量或者临时变量赋值
+
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum QuadOp {
+    /// 运算符
+    Operator(Operator),
+    /// 传递函数调用的一个参数
+    Param,
+    /// 调用函数
+    Call {
+        /// 函数名
+        name: String,
+        /// 参数个数
+        arity: usize,
+    },
+    /// 定义变量
+    Let,
+    /// 给已经定义的变量或者临时变量赋值
+    Assign,
+}
+
+/**
+四元式 `(op, arg1, arg2, result)` 。
+
+* `op` 运算
+* `arg1` 第一个运算对象，函数调用时为 `None`
+* `arg2` 第二个运算对象，单目运算、传参和赋值时为 `None`
+* `result` 保存结果的临时变量或者变量，传参时为 `None`
+* `span` 运算在输入串中的区间，出错时指出这里
+
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quadruple {
+    /// 运算
+    pub op: QuadOp,
+    /// 第一个运算对象，函数调用时为 `None`
+    pub arg1: Option<Operand>,
+    /// 第二个运算对象，单目运算、传参和赋值时为 `None`
+    pub arg2: Option<Operand>,
+    /// 保存结果的临时变量或者变量，传参时为 `None`
+    pub result: Option<Operand>,
+    /// 运算在输入串中的区间，出错时指出这里
+    pub span: Span,
+}
+
+/**
+三元式 `(op, arg1, arg2)` ，结果就是三元式自己，用编号引用。
+
+给变量赋值时 `arg1` 是变量，`arg2` 是值。
+
+* `op` 运算
+* `arg1` 第一个运算对象
+* `arg2` 第二个运算对象
+
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Triple {
+    /// 运算
+    pub op: QuadOp,
+    /// 第一个运算对象
+    pub arg1: Option<Operand>,
+    /// 第二个运算对象
+    pub arg2: Option<Operand>,
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            // 数字的格式和逆波兰式相同
+            Operand::Number { value, .. } => write!(f, "{}", value.normalized()),
+            Operand::Variable { name, .. } => write!(f, "{}", name),
+            Operand::Temp(index) => write!(f, "t{}", index),
+            Operand::Ref(index) => write!(f, "({})", index),
+        }
+    }
+}
+
+impl fmt::Display for QuadOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QuadOp::Operator(op) => write!(f, "{}", op),
+            QuadOp::Param => write!(f, "param"),
+            QuadOp::Call { .. } => write!(f, "call"),
+            QuadOp::Let => write!(f, "let"),
+            QuadOp::Assign => write!(f, "="),
+        }
+    }
+}
+
+/// 运算对象的文本，没有时为 `_` ；函数调用的两个运算对象是函数名和参数个数
+fn operand_cells(op: &QuadOp, arg1: &Option<Operand>, arg2: &Option<Operand>) -> [String; 2] {
+    if let QuadOp::Call { name, arity } = op {
+        return [name.clone(), arity.to_string()];
+    }
+    let text = |arg: &Option<Operand>| match arg {
+        Some(arg) => arg.to_string(),
+        None => "_".to_owned(),
+    };
+    return [text(arg1), text(arg2)];
+}
+
+impl Quadruple {
+    /** 四元式的四栏文本，依次为运算、两个运算对象和结果，没有的栏为 `_` 。 */
+    pub fn cells(&self) -> [String; 4] {
+        let [arg1, arg2] = operand_cells(&self.op, &self.arg1, &self.arg2);
+        let result = match &self.result {
+            Some(result) => result.to_string(),
+            None => "_".to_owned(),
+        };
+        return [self.op.to_string(), arg1, arg2, result];
+    }
+}
+
+impl Triple {
+    /** 三元式的三栏文本，依次为运算和两个运算对象，没有的栏为 `_` 。 */
+    pub fn cells(&self) -> [String; 3] {
+        let [arg1, arg2] = operand_cells(&self.op, &self.arg1, &self.arg2);
+        return [self.op.to_string(), arg1, arg2];
+    }
+}
+
+impl fmt::Display for Quadruple {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "({})", self.cells().join(", "))
+    }
+}
+
+impl fmt::Display for Triple {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "({})", self.cells().join(", "))
+    }
+}
+
+/**
+生成四元式的工具，临时变量在整个程序中统一编号。
+
+* `output` 已经生成的四元式
+* `temps` 已经使用的临时变量个数
+
+ */
+#[derive(Default)]
+struct Generator {
+    /// 已经生成的四元式
+    output: Vec<Quadruple>,
+    /// 已经使用的临时变量个数
+    temps: usize,
+}
+
+impl Generator {
+    /// 申请一个新的临时变量
+    fn temp(&mut self) -> Operand {
+        self.temps += 1;
+        return Operand::Temp(self.temps);
+    }
+
+    fn push(&mut self, op: QuadOp, arg1: Option<Operand>, arg2: Option<Operand>, span: Span) {
+        let result = match op {
+            QuadOp::Param => None,
+            _ => Some(self.temp()),
+        };
+        self.output.push(Quadruple {
+            op,
+            arg1,
+            arg2,
+            result,
+            span,
+        });
+    }
+
+    /// 生成计算表达式的四元式，返回保存表达式的值的运算对象，叶子直接作为运算对象
+    fn expr(&mut self, expr: &Expr) -> Operand {
+        match &expr.kind {
+            ExprKind::Number(n) => {
+                return Operand::Number {
+                    value: n.clone(),
+                    span: expr.span,
+                };
+            }
+            ExprKind::Variable(name) => {
+                return Operand::Variable {
+                    name: name.clone(),
+                    span: expr.span,
+                };
+            }
+            ExprKind::Unary {
+                op,
+                op_span,
+                operand,
+            } => {
+                let arg = self.expr(operand);
+                self.push(QuadOp::Operator(*op), Some(arg), None, *op_span);
+            }
+            ExprKind::Binary {
+                op,
+                op_span,
+                left,
+                right,
+            } => {
+                let left = self.expr(left);
+                let right = self.expr(right);
+                self.push(QuadOp::Operator(*op), Some(left), Some(right), *op_span);
+            }
+            ExprKind::Call { name, args, .. } => {
+                // 先算出所有参数，再依次传参，嵌套的调用不会打乱参数
+                let args: Vec<Operand> = args.iter().map(|arg| self.expr(arg)).collect();
+                let arity = args.len();
+                for arg in args {
+                    self.push(QuadOp::Param, Some(arg), None, expr.span);
+                }
+                let name = name.clone();
+                self.push(QuadOp::Call { name, arity }, None, None, expr.span);
+            }
+        }
+        return self.output.last().unwrap().result.clone().unwrap();
+    }
+
+    /// 生成一条语句的四元式，最后一个四元式的结果就是语句的值
+    fn stmt(&mut self, stmt: &Stmt) {
+        let (op, name, name_span) = match &stmt.kind {
+            StmtKind::Let {
+                name, name_span, ..
+            } => (QuadOp::Let, name, name_span),
+            StmtKind::Assign {
+                name, name_span, ..
+            } => (QuadOp::Assign, name, name_span),
+            StmtKind::Expr(expr) => {
+                let value = self.expr(expr);
+                if matches!(value, Operand::Number { .. } | Operand::Variable { .. }) {
+                    // 单独的叶子没有生成四元式，赋值给临时变量作为语句的值
+                    self.push(QuadOp::Assign, Some(value), None, expr.span);
+                }
+                return;
+            }
+        };
+
+        let value = self.expr(stmt.value());
+        self.output.push(Quadruple {
+            op,
+            arg1: Some(value),
+            arg2: None,
+            result: Some(Operand::Variable {
+                name: name.clone(),
+                span: *name_span,
+            }),
+            span: stmt.span,
+        });
+    }
+}
+
+/**
+把多条语句翻译为四元式，临时变量在整个程序中统一编号。
+
+单独的表达式的值保存在临时变量中，`let` 和赋值语句的值直接保存在变量中。
+
+* `program` 语法分析得到的语句
+ */
+pub fn generate(program: &[Stmt]) -> Vec<Quadruple> {
+    let mut generator = Generator::default();
+    for stmt in program {
+        generator.stmt(stmt);
+    }
+    return generator.output;
+}
+
+/**
+把四元式改写为三元式。
+
+临时变量改为引用算出它的三元式的编号；给变量赋值的四元式 `(op, value, _, x)` 改写为 `(op, x, value)` 。
+
+* `quadruples` [`generate`] 生成的四元式
+ */
+pub fn to_triples(quadruples: &[Quadruple]) -> Vec<Triple> {
+    // 每个临时变量由哪个四元式算出
+    let mut defined_at = HashMap::new();
+    for (index, quadruple) in quadruples.iter().enumerate() {
+        if let Some(Operand::Temp(temp)) = quadruple.result {
+            defined_at.insert(temp, index);
+        }
+    }
+    let rewrite = |arg: &Option<Operand>| match arg {
+        Some(Operand::Temp(temp)) => Some(Operand::Ref(defined_at[temp])),
+        _ => arg.clone(),
+    };
+
+    return quadruples
+        .iter()
+        .map(|quadruple| match &quadruple.result {
+            Some(variable @ Operand::Variable { .. }) => Triple {
+                op: quadruple.op.clone(),
+                arg1: Some(variable.clone()),
+                arg2: rewrite(&quadruple.arg1),
+            },
+            _ => Triple {
+                op: quadruple.op.clone(),
+                arg1: rewrite(&quadruple.arg1),
+                arg2: rewrite(&quadruple.arg2),
+            },
+        })
+        .collect();
+}
+
+/**
+依次执行四元式，返回最后一个四元式的结果，也就是最后一条语句的值，没有四元式时为 `None` 。
+
+每个运算和逆波兰式的计算共用同一份实现，所以能算出结果时和 [`crate::calculate::calculate_with_options`]
+的结果相同。叶子在用到时才取值，有多处错误时报告的可能和逆波兰式不是同一处。
+
+* `quadruples` [`generate`] 生成的四元式
+* `env` 计算环境，变量从这里取值，执行完后保留所有定义的变量
+* `options` 计算的选项
+ */
+pub fn execute_quadruples<N: Numeric>(
+    quadruples: &[Quadruple],
+    env: &mut Env<N>,
+    options: &EvalOptions,
+) -> Result<Option<N>, CalcError> {
+    let mut temps: HashMap<usize, N> = HashMap::new();
+    // 已经传递、还没有被调用取走的参数
+    let mut params: Vec<N> = vec![];
+    let mut last = None;
+
+    for quadruple in quadruples {
+        // 借用逆波兰式的运算栈：先放入运算对象，再执行运算
+        let mut stack = VecDeque::new();
+        for arg in quadruple.arg1.iter().chain(quadruple.arg2.iter()) {
+            push_operand(arg, &mut stack, &temps, env, options)?;
+        }
+
+        let span = quadruple.span;
+        match &quadruple.op {
+            QuadOp::Operator(op) => {
+                let item = RPNItem::Operator { op: *op, span };
+                execute(&item, &mut stack, env, options)?;
+            }
+            QuadOp::Param => {
+                params.extend(stack);
+                continue;
+            }
+            QuadOp::Call { name, arity } => {
+                if params.len() < *arity {
+                    return Err(CalcError::StackUnderflow(span));
+                }
+                stack.extend(params.split_off(params.len() - arity));
+                let item = RPNItem::Call {
+                    name: name.clone(),
+                    arity: *arity,
+                    span,
+                };
+                execute(&item, &mut stack, env, options)?;
+            }
+            QuadOp::Let | QuadOp::Assign => {}
+        }
+
+        let value = stack.pop_back().ok_or(CalcError::StackUnderflow(span))?;
+        match &quadruple.result {
+            Some(Operand::Temp(temp)) => {
+                temps.insert(*temp, value.clone());
+            }
+            Some(Operand::Variable { name, span }) => {
+                if quadruple.op == QuadOp::Assign && env.get(name).is_none() {
+                    // 和执行语句时一样，赋值前变量必须已经定义
+                    return Err(CalcError::UndefinedAssignment {
+                        name: name.clone(),
+                        span: *span,
+                    });
+                }
+                env.set(name, value.clone());
+            }
+            _ => {}
+        }
+        last = Some(value);
+    }
+
+    return Ok(last);
+}
+
+/// 取出运算对象的值 push_back 进入运算栈，数字和变量按逆波兰式中的元素执行
+fn push_operand<N: Numeric>(
+    operand: &Operand,
+    stack: &mut VecDeque<N>,
+    temps: &HashMap<usize, N>,
+    env: &Env<N>,
+    options: &EvalOptions,
+) -> Result<(), CalcError> {
+    let item = match operand {
+        Operand::Number { value, span } => RPNItem::Number {
+            value: value.clone(),
+            span: *span,
+        },
+        Operand::Variable { name, span } => RPNItem::Variable {
+            name: name.clone(),
+            span: *span,
+        },
+        Operand::Temp(temp) => {
+            // 临时变量总是在用到之前算出
+            stack.push_back(temps[temp].clone());
+            return Ok(());
+        }
+        Operand::Ref(_) => unreachable!("四元式中不会出现三元式的引用"),
+    };
+    return execute(&item, stack, env, options);
+}
+
+#[cfg(test)]
+mod test {
+    use super::{execute_quadruples, generate, to_triples};
+    use crate::calculate::{calculate_with_options, CalcError, Env};
+    use crate::lower::lower;
+    use crate::options::EvalOptions;
+    use crate::parse::{parse, parse_program};
+    use crate::token::tokenization;
+    use crate::token_render::*;
+    use bigdecimal::BigDecimal;
+    use num_rational::BigRational;
+
+    #[test]
+    fn generate_test() {
+        // 测试生成的四元式和三元式
+        let input = "let x = -2; max(1, x * 3) + x";
+        let tokens = tokenization(input).unwrap();
+        let program = parse_program(&mut TokenRender::new_with_tokens(tokens)).unwrap();
+        let quadruples = generate(&program);
+        let expected = vec![
+            "(@, 2, _, t1)",
+            "(let, t1, _, x)",
+            "(*, x, 3, t2)",
+            "(param, 1, _, _)",
+            "(param, t2, _, _)",
+            "(call, max, 2, t3)",
+            "(+, t3, x, t4)",
+        ];
+        let output: Vec<String> = quadruples.iter().map(|q| q.to_string()).collect();
+        assert_eq!(output, expected);
+
+        let expected = vec![
+            "(@, 2, _)",
+            "(let, x, (0))",
+            "(*, x, 3)",
+            "(param, 1, _)",
+            "(param, (2), _)",
+            "(call, max, 2)",
+            "(+, (5), x)",
+        ];
+        let output: Vec<String> = to_triples(&quadruples)
+            .iter()
+            .map(|t| t.to_string())
+            .collect();
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn execute_quadruples_test() {
+        // 测试执行四元式的结果和计算逆波兰式的结果相同
+        let input_vec = vec![
+            "56+88-9999",
+            "3*4*5/(-2)",
+            "-(2+3)^2",
+            "2**3^2",
+            "max(1, -2, 3+4) // 2",
+            "min(max(1, 2), 3, sqrt(16)) % 3",
+            "7",
+            "1/3",
+            "5 / (2 - 2)",
+            "sqrt(-1)",
+            "2 ^ 0.5",
+        ];
+        let options = EvalOptions::default();
+        for input in input_vec {
+            let tokens = tokenization(input).unwrap();
+            let expr = parse(&mut TokenRender::new_with_tokens(tokens.clone())).unwrap();
+            let program = parse_program(&mut TokenRender::new_with_tokens(tokens)).unwrap();
+            let quadruples = generate(&program);
+
+            let expected = calculate_with_options(&lower(&expr), &Env::new(), &options);
+            let output = execute_quadruples(&quadruples, &mut Env::new(), &options);
+            assert_eq!(output, expected.map(Some), "input: {}", input);
+
+            let expected =
+                calculate_with_options::<BigRational>(&lower(&expr), &Env::default(), &options);
+            let output = execute_quadruples(&quadruples, &mut Env::default(), &options);
+            assert_eq!(output, expected.map(Some), "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn execute_program_test() {
+        // 测试执行多条语句时变量的定义和赋值
+        let input_vec = vec![
+            ("let x = 2; x = x * 3; x + 1", Some("7")),
+            ("let x = 2; x", Some("2")),
+            ("y = 1", None),
+        ];
+        for (input, expected) in input_vec {
+            let tokens = tokenization(input).unwrap();
+            let program = parse_program(&mut TokenRender::new_with_tokens(tokens)).unwrap();
+            let output = execute_quadruples(
+                &generate(&program),
+                &mut Env::new(),
+                &EvalOptions::default(),
+            );
+            match (output, expected) {
+                (Ok(Some(n)), Some(expected)) => {
+                    assert_eq!(
+                        n,
+                        expected.parse::<BigDecimal>().unwrap(),
+                        "input: {}",
+                        input
+                    )
+                }
+                // 给没有定义的变量赋值
+                (Err(CalcError::UndefinedAssignment { .. }), None) => {}
+                (output, _) => panic!("input: {}, output: {:?}", input, output),
+            }
+        }
+    }
+}
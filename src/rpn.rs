@@ -0,0 +1,265 @@
+use crate::parse::{Operator, RPNItem};
+use crate::token::{tokenization, Span, TokenInfo};
+use std::error::Error;
+use std::fmt;
+
+/**
+解析逆波兰式文本时的错误。
+
+* `RpnErr::InvalidItem` 不是数字、变量、运算符或者函数调用
+* `RpnErr::StackUnderflow` 执行到运算符或者函数调用时运算栈中的数字不够用
+* `RpnErr::LeftoverOperands` 计算结束后运算栈中剩下不止一个数字
+* `RpnErr::Empty` 没有任何元素
+
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub enum RpnErr {
+    /// 不是数字、变量、运算符或者函数调用
+    InvalidItem {
+        /// 元素原文本
+        text: String,
+        /// 错误原因
+        reason: String,
+        /// 元素在输入串中的区间
+        span: Span,
+    },
+    /// 运算栈中的数字不够用
+    StackUnderflow {
+        /// 元素原文本
+        text: String,
+        /// 需要的运算对象个数
+        needed: usize,
+        /// 运算栈中的数字个数
+        found: usize,
+        /// 元素在输入串中的区间
+        span: Span,
+    },
+    /// 计算结束后运算栈中剩下不止一个数字
+    LeftoverOperands {
+        /// 没有被用到的每个值是由哪个元素算出来的，栈底在前
+        spans: Vec<Span>,
+    },
+    /// 没有任何元素
+    Empty,
+}
+
+impl fmt::Display for RpnErr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RpnErr::InvalidItem { text, .. } => write!(f, "无法识别逆波兰式元素 {}", text),
+            RpnErr::StackUnderflow { text, .. } => {
+                write!(f, "执行 {} 时运算栈中的数字不够用", text)
+            }
+            RpnErr::LeftoverOperands { spans } => write!(
+                f,
+                "计算结束后运算栈中剩下 {} 个数字，缺少运算符",
+                spans.len() + 1
+            ),
+            RpnErr::Empty => write!(f, "逆波兰式中没有任何元素"),
+        }
+    }
+}
+
+impl Error for RpnErr {}
+
+/**
+解析逆波兰式文本，元素之间用空白分隔，写法和 `RPNItem` 的输出相同。
+
+数字可以带负号，如 `-6` ；`@` 是取负；函数调用写作 `函数名:参数个数` ，如 `max:2` 。
+解析时按元素的顺序检查运算栈的深度，数字不够用或者剩下多个数字时指出对应的元素，
+所以解析成功的逆波兰式计算时不会再遇到这两种错误。
+每个元素的区间是它在 `input` 中的位置，计算出错时的位置也指向 `input` 中的元素。
+
+* `input` 逆波兰式文本，如 `3 4 + 2 *`
+ */
+pub fn parse_rpn(input: &str) -> Result<Vec<RPNItem>, RpnErr> {
+    let mut items = vec![];
+    // 运算栈中每个值是由哪个元素算出来的
+    let mut stack: Vec<Span> = vec![];
+
+    for (text, span) in split_words(input) {
+        let item = parse_item(text, span)?;
+        let needed = match &item {
+            RPNItem::Number { .. } | RPNItem::Variable { .. } => 0,
+            RPNItem::Operator {
+                op: Operator::Minus,
+                ..
+            } => 1,
+            RPNItem::Operator { .. } => 2,
+            RPNItem::Call { arity, .. } => *arity,
+        };
+        if stack.len() < needed {
+            return Err(RpnErr::StackUnderflow {
+                text: text.to_owned(),
+                needed,
+                found: stack.len(),
+                span,
+            });
+        }
+        stack.truncate(stack.len() - needed);
+        stack.push(span);
+        items.push(item);
+    }
+
+    match stack.len() {
+        0 => return Err(RpnErr::Empty),
+        1 => return Ok(items),
+        // 最后一个值是结果，前面的都没有被用到
+        n => {
+            stack.truncate(n - 1);
+            return Err(RpnErr::LeftoverOperands { spans: stack });
+        }
+    }
+}
+
+/// 按空白把输入分成元素，返回每个元素和它的区间
+fn split_words(input: &str) -> Vec<(&str, Span)> {
+    let mut words = vec![];
+    let mut start = None;
+    for (index, c) in input.char_indices().chain([(input.len(), ' ')]) {
+        match (start, c.is_whitespace()) {
+            (None, false) => start = Some(index),
+            (Some(begin), true) => {
+                words.push((&input[begin..index], Span::new(begin, index)));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    return words;
+}
+
+/// 解析一个元素
+fn parse_item(text: &str, span: Span) -> Result<RPNItem, RpnErr> {
+    let op = match text {
+        "+" => Some(Operator::Add),
+        "-" => Some(Operator::Sub),
+        "*" => Some(Operator::Mul),
+        "/" => Some(Operator::Div),
+        "^" | "**" => Some(Operator::Pow),
+        "%" => Some(Operator::Mod),
+        "//" => Some(Operator::IntDiv),
+        "@" => Some(Operator::Minus),
+        _ => None,
+    };
+    if let Some(op) = op {
+        return Ok(RPNItem::Operator { op, span });
+    }
+
+    let invalid = |reason: &str| RpnErr::InvalidItem {
+        text: text.to_owned(),
+        reason: reason.to_owned(),
+        span,
+    };
+
+    if let Some((name, arity)) = text.split_once(':') {
+        // 函数调用
+        if !matches!(single_token(name), Some(TokenInfo::Identifier(_))) {
+            return Err(invalid("冒号前面应该是函数名"));
+        }
+        let arity = arity
+            .parse::<usize>()
+            .map_err(|_| invalid("冒号后面应该是参数个数"))?;
+        return Ok(RPNItem::Call {
+            name: name.to_owned(),
+            arity,
+            span,
+        });
+    }
+
+    // 带负号的数字，如化简后的 `-6`
+    let (negative, rest) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+    match single_token(rest) {
+        Some(TokenInfo::Number(value)) => {
+            let value = if negative { -value } else { value };
+            return Ok(RPNItem::Number { value, span });
+        }
+        Some(TokenInfo::Identifier(name)) if !negative => {
+            return Ok(RPNItem::Variable { name, span });
+        }
+        _ => return Err(invalid("这不是数字、变量、运算符或者函数调用")),
+    }
+}
+
+/// 用词法分析器分析一段文本，恰好是一个数字或者标识符时返回它的信息
+fn single_token(text: &str) -> Option<TokenInfo> {
+    let mut tokens = tokenization(text).ok()?;
+    if tokens.len() != 1 {
+        return None;
+    }
+    return Some(tokens.remove(0).info);
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_rpn, RpnErr};
+    use crate::calculate::{calculate, CalcError};
+    use crate::token::Span;
+    use bigdecimal::BigDecimal;
+    use std::str::FromStr;
+
+    #[test]
+    fn parse_rpn_test() {
+        // 测试解析后的逆波兰式和计算结果
+        let input_vec = vec![
+            ("3 4 + 2 *", "3 4 + 2 *", "14"),
+            ("  2 @   3 ** ", "2 @ 3 ^", "-8"),
+            ("-6 .5 /", "-6 0.5 /", "-12"),
+            ("1 2 3 max:3 7 // 1e1 %", "1 2 3 max:3 7 // 10 %", "0"),
+            ("7 @ 2 %", "7 @ 2 %", "1"),
+            ("\t9\nsqrt:1", "9 sqrt:1", "3"),
+        ];
+        for (input, expected, value) in input_vec {
+            let items = parse_rpn(input).unwrap();
+            let text: Vec<String> = items.iter().map(|item| item.to_string()).collect();
+            assert_eq!(text.join(" "), expected, "input: {}", input);
+            let value = BigDecimal::from_str(value).unwrap();
+            assert_eq!(calculate(&items), Ok(value), "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn parse_rpn_test_fail() {
+        // 测试错误指出的元素
+        let input_vec = vec![
+            ("3 +", Some(2..3)),
+            ("1 2 + max:3", Some(6..11)),
+            ("@", Some(0..1)),
+            ("3 4 $ +", Some(4..5)),
+            ("3 sqrt:x", Some(2..8)),
+            ("3 1x:1", Some(2..6)),
+            ("-x", Some(0..2)),
+            ("", None),
+        ];
+        for (input, expected) in input_vec {
+            let span = match parse_rpn(input) {
+                Err(RpnErr::StackUnderflow { span, .. }) => Some(span),
+                Err(RpnErr::InvalidItem { span, .. }) => Some(span),
+                Err(RpnErr::Empty) => None,
+                other => panic!("input: {}, result: {:?}", input, other),
+            };
+            assert_eq!(span.map(|s| s.start..s.end), expected, "input: {}", input);
+        }
+
+        // 剩下的数字指向算出它们的元素
+        match parse_rpn("1 2 3 * 4") {
+            Err(RpnErr::LeftoverOperands { spans }) => {
+                assert_eq!(spans, [Span::new(0, 1), Span::new(6, 7)])
+            }
+            other => panic!("1 2 3 * 4 应该剩下数字，却得到了 {:?}", other),
+        }
+    }
+
+    #[test]
+    fn calculate_rpn_error_test() {
+        // 测试计算出错时指向逆波兰式中的元素
+        let items = parse_rpn("1 2 2 - /").unwrap();
+        assert_eq!(
+            calculate(&items),
+            Err(CalcError::DivisionByZero(Span::new(8, 9)))
+        );
+    }
+}
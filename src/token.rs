@@ -1,6 +1,6 @@
 use bigdecimal::BigDecimal;
 use once_cell::sync::Lazy;
-use std::{collections::HashMap, fmt};
+use std::{collections::HashMap, fmt, ops::Range};
 
 type Num = BigDecimal;
 
@@ -8,8 +8,11 @@ type Num = BigDecimal;
 static DEFAULE_TOKEN_LIST: Lazy<HashMap<u8, TokenInfo>> = Lazy::new(|| {
     let mut data: HashMap<u8, TokenInfo> = HashMap::new();
     data.insert(b' ', TokenInfo::Symbol(SymbolType::Blank));
-    data.insert(b'\n', TokenInfo::Symbol(SymbolType::Blank));
     data.insert(b'\t', TokenInfo::Symbol(SymbolType::Blank));
+    data.insert(b'\r', TokenInfo::Symbol(SymbolType::Blank));
+    data.insert(b'\n', TokenInfo::Symbol(SymbolType::Newline));
+    data.insert(b';', TokenInfo::Symbol(SymbolType::Semicolon));
+    data.insert(b'=', TokenInfo::Symbol(SymbolType::Assign));
 
     data.insert(b'(', TokenInfo::Symbol(SymbolType::LeftBracket));
     data.insert(b')', TokenInfo::Symbol(SymbolType::RightBracket));
@@ -17,6 +20,17 @@ static DEFAULE_TOKEN_LIST: Lazy<HashMap<u8, TokenInfo>> = Lazy::new(|| {
     data.insert(b'-', TokenInfo::Symbol(SymbolType::Sub));
     data.insert(b'*', TokenInfo::Symbol(SymbolType::Mul));
     data.insert(b'/', TokenInfo::Symbol(SymbolType::Div));
+    data.insert(b'^', TokenInfo::Symbol(SymbolType::Pow));
+    data.insert(b'%', TokenInfo::Symbol(SymbolType::Mod));
+    data.insert(b',', TokenInfo::Symbol(SymbolType::Comma));
+    data
+});
+
+// 由两个字符组成的符号的对应表，优先于单个字符的符号匹配
+static DEFAULE_DOUBLE_TOKEN_LIST: Lazy<HashMap<(u8, u8), TokenInfo>> = Lazy::new(|| {
+    let mut data: HashMap<(u8, u8), TokenInfo> = HashMap::new();
+    data.insert((b'*', b'*'), TokenInfo::Symbol(SymbolType::Pow));
+    data.insert((b'/', b'/'), TokenInfo::Symbol(SymbolType::IntDiv));
     data
 });
 
@@ -38,10 +52,51 @@ pub struct Token {
     pub original_str: String,
 }
 
+impl Token {
+    /** 当前符号在输入串中所占的区间。 */
+    pub fn span(&self) -> Span {
+        return Span::new(self.position, self.position + self.original_str.len());
+    }
+}
+
+/**
+输入串中的一段区间 `[start, end)` 。
+
+* `start` 区间起始位置（包含）
+* `end` 区间结束位置（不包含）
+
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    /// 区间起始位置（包含）
+    pub start: usize,
+    /// 区间结束位置（不包含）
+    pub end: usize,
+}
+
+impl Span {
+    /** 用起止位置初始化一个 Span 。 */
+    pub fn new(start: usize, end: usize) -> Span {
+        return Span { start, end };
+    }
+
+    /** 返回从 `self` 开头到 `other` 结尾的区间。 */
+    pub fn to(&self, other: Span) -> Span {
+        return Span::new(self.start, other.end);
+    }
+}
+
+impl From<Span> for Range<usize> {
+    fn from(span: Span) -> Range<usize> {
+        return span.start..span.end;
+    }
+}
+
 /**
 符号信息。
 
 * `TokenInfo::Number` 是数字类型。
+* `TokenInfo::Identifier` 是标识符类型。
 * `TokenInfo::Symbol` 是符号类型。
 
  */
@@ -49,6 +104,8 @@ pub struct Token {
 pub enum TokenInfo {
     /// 数字类型
     Number(Num),
+    /// 标识符类型，如函数名
+    Identifier(String),
     /// 符号类型
     Symbol(SymbolType),
 }
@@ -63,6 +120,14 @@ pub enum TokenInfo {
 * `SymbolType::Sub` 是减。
 * `SymbolType::Mul` 是乘。
 * `SymbolType::Div` 是除。
+* `SymbolType::Pow` 是乘方。
+* `SymbolType::Mod` 是取余。
+* `SymbolType::IntDiv` 是整除。
+* `SymbolType::Comma` 是逗号。
+* `SymbolType::Newline` 是换行，用于分隔语句。
+* `SymbolType::Semicolon` 是分号，用于分隔语句。
+* `SymbolType::Assign` 是赋值号。
+* `SymbolType::Let` 是 let 关键字。
 
 */
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -81,6 +146,22 @@ pub enum SymbolType {
     Mul,
     /// 除
     Div,
+    /// 乘方
+    Pow,
+    /// 取余
+    Mod,
+    /// 整除
+    IntDiv,
+    /// 逗号
+    Comma,
+    /// 换行
+    Newline,
+    /// 分号
+    Semicolon,
+    /// 赋值号
+    Assign,
+    /// let 关键字
+    Let,
 }
 
 impl fmt::Display for TokenInfo {
@@ -94,10 +175,19 @@ impl fmt::Display for TokenInfo {
                 SymbolType::Sub => write!(f, "减号"),
                 SymbolType::Mul => write!(f, "乘号"),
                 SymbolType::Div => write!(f, "除号"),
+                SymbolType::Pow => write!(f, "乘方号"),
+                SymbolType::Mod => write!(f, "取余号"),
+                SymbolType::IntDiv => write!(f, "整除号"),
+                SymbolType::Comma => write!(f, "逗号"),
+                SymbolType::Newline => write!(f, "换行符"),
+                SymbolType::Semicolon => write!(f, "分号"),
+                SymbolType::Assign => write!(f, "赋值号"),
+                SymbolType::Let => write!(f, "关键字 let"),
             },
             TokenInfo::Number(n) => {
-                write!(f, "数字：{}", n.with_scale(15).normalized().to_string())
+                write!(f, "数字：{}", n.normalized().to_string())
             }
+            TokenInfo::Identifier(name) => write!(f, "标识符：{}", name),
         }
     }
 }
@@ -108,7 +198,7 @@ impl fmt::Display for TokenInfo {
 * `reason` 错误原因
 * `position` 错误发生的位置
  */
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct LexerErr {
     /// 错误原因
     pub reason: String,
@@ -118,6 +208,45 @@ pub struct LexerErr {
     pub original_str: String,
 }
 
+/**
+解析标识符，标识符以字母或下划线开头，由字母、数字和下划线组成，关键字会被解析为符号
+* `input` 标识符字符串
+* `start_position` 输入字符串在输入串中的位置
+*/
+fn parse_identifier_token(input: &str, start_position: usize) -> Result<Token, String> {
+    if input == "let" {
+        return Ok(Token {
+            position: start_position,
+            info: TokenInfo::Symbol(SymbolType::Let),
+            original_str: input.to_owned(),
+        });
+    }
+    if input
+        .bytes()
+        .all(|c| c.is_ascii_alphanumeric() || c == b'_')
+    {
+        return Ok(Token {
+            position: start_position,
+            info: TokenInfo::Identifier(input.to_owned()),
+            original_str: input.to_owned(),
+        });
+    }
+    return Err(format!("遇到未预期的输入: '{}' ！", input).to_owned());
+}
+
+/**
+解析数字或标识符，以字母或下划线开头的视为标识符，其他的视为数字
+* `input` 数字或标识符字符串
+* `start_position` 输入字符串在输入串中的位置
+*/
+fn parse_word_token(input: &str, start_position: usize) -> Result<Token, String> {
+    let first = input.as_bytes()[0];
+    if first.is_ascii_alphabetic() || first == b'_' {
+        return parse_identifier_token(input, start_position);
+    }
+    return parse_number_token(input, start_position);
+}
+
 /**
 解析数字
 * `input` 数字字符串
@@ -139,32 +268,65 @@ fn parse_number_token(input: &str, start_position: usize) -> Result<Token, Strin
 }
 
 /**
-符号化字符串/对字符串分词。
+符号化字符串/对字符串分词，遇到第一个错误就返回。
 
 * `input` 被分词的字符串
  */
 pub fn tokenization(input: &str) -> Result<Vec<Token>, LexerErr> {
+    let (tokens, mut errors) = tokenization_recovering(input);
+
+    if errors.is_empty() {
+        return Ok(tokens);
+    } else {
+        return Err(errors.remove(0));
+    }
+}
+
+/**
+符号化字符串/对字符串分词，遇到错误时跳过出错的部分继续分词。
+
+返回所有成功解析的 token 和所有错误，错误按位置排序。
+
+* `input` 被分词的字符串
+ */
+pub fn tokenization_recovering(input: &str) -> (Vec<Token>, Vec<LexerErr>) {
     let mut tokens = vec![]; // 存放解析结果
+    let mut errors = vec![]; // 存放错误
     let mut unparsed_position: usize = 0; // 目前第一个未解析符号
 
     for current_position in 0..input.len() + 1 {
+        if current_position < unparsed_position {
+            // 这个位置已经作为双字符符号的一部分解析过了
+            continue;
+        }
+
         // 如果没有到达结尾
         if current_position != input.len() {
             // 当前解析的符号
             let item = input.as_bytes()[current_position];
 
+            // 先看看当前符号和下一个符号能不能组成双字符符号
+            let double_token = input
+                .as_bytes()
+                .get(current_position + 1)
+                .and_then(|next| DEFAULE_DOUBLE_TOKEN_LIST.get(&(item, *next)));
+
+            // 符号的长度
+            let token_len = if double_token.is_some() { 2 } else { 1 };
+
             // 看看当前解析的符号在不在符号表里面
-            if let Some(token) = DEFAULE_TOKEN_LIST.get(&item) {
-                // 如果第一个未解析符号不等于当前符号，证明它们之间有一些符号需要尝试解析为数字
+            if let Some(token) = double_token.or_else(|| DEFAULE_TOKEN_LIST.get(&item)) {
+                // 如果第一个未解析符号不等于当前符号，证明它们之间有一些符号需要尝试解析为数字或标识符
                 if unparsed_position != current_position {
-                    // 解析数字
-                    match parse_number_token(
+                    // 解析数字或标识符
+                    match parse_word_token(
                         &input[unparsed_position..current_position],
                         unparsed_position,
                     ) {
                         Ok(item) => tokens.push(item),
                         Err(reason) => {
-                            return Err(LexerErr {
+                            // 记下错误，跳过这一段继续解析
+                            errors.push(LexerErr {
                                 reason,
                                 position: unparsed_position,
                                 original_str: input[unparsed_position..current_position].to_owned(),
@@ -183,22 +345,24 @@ pub fn tokenization(input: &str) -> Result<Vec<Token>, LexerErr> {
                         tokens.push(Token {
                             position: current_position,
                             info: token.clone(),
-                            original_str: input[current_position..current_position + 1].to_owned(),
+                            original_str: input[current_position..current_position + token_len]
+                                .to_owned(),
                         });
                     }
                 }
 
                 // 更新第一个未解析符号位置
-                unparsed_position = current_position + 1;
+                unparsed_position = current_position + token_len;
             }
         } else {
-            // 如果还有需要解析的符号，证明这西符号需要尝试解析为数字
+            // 如果还有需要解析的符号，证明这西符号需要尝试解析为数字或标识符
             if unparsed_position != current_position {
-                // 解析数字
-                match parse_number_token(&input[unparsed_position..], unparsed_position) {
+                // 解析数字或标识符
+                match parse_word_token(&input[unparsed_position..], unparsed_position) {
                     Ok(item) => tokens.push(item),
                     Err(reason) => {
-                        return Err(LexerErr {
+                        // 记下错误，已经到达结尾了
+                        errors.push(LexerErr {
                             reason,
                             position: unparsed_position,
                             original_str: input[unparsed_position..current_position].to_owned(),
@@ -209,13 +373,13 @@ pub fn tokenization(input: &str) -> Result<Vec<Token>, LexerErr> {
         }
     }
 
-    Ok(tokens)
+    (tokens, errors)
 }
 
 // 单元测试
 #[cfg(test)]
 mod test {
-    use super::tokenization;
+    use super::{tokenization, tokenization_recovering};
 
     #[test]
     fn parse_test_success() {
@@ -239,6 +403,21 @@ mod test {
             "( )34",
             "13.",
             "   1",
+            "2^3",
+            "2**3",
+            "2***3",
+            "2*",
+            "7%3",
+            "7//2",
+            "7///2",
+            "u",
+            "sqrt(2)",
+            "max(1, 2)",
+            "_x1",
+            "13, + 4",
+            "let x = 1; x",
+            "a\nb",
+            "1\r\n2",
         ];
         for i in 0..input_vec.len() {
             let re = tokenization(input_vec[i]);
@@ -252,16 +431,16 @@ mod test {
         let input_vec = vec![
             "56+88-99a99",
             "1a9",
-            "u",
             "1231+67a8",
             "1e",
-            "13, + 4",
             "   ",
             "6+12a",
             "12a",
             "12.2e3.4",
+            "2+sq.rt(2)",
+            "2sqrt(2)",
         ];
-        let index_vec = vec![6, 0, 0, 5, 0, 0, 0, 2, 0, 0];
+        let index_vec = vec![6, 0, 5, 0, 0, 2, 0, 0, 2, 0];
         assert!(index_vec.len() == input_vec.len());
         for i in 0..index_vec.len() {
             let re = tokenization(input_vec[i]);
@@ -276,4 +455,21 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn parse_test_recovering() {
+        // 测试出错后继续分词，收集所有错误的位置和剩下的 token 数量
+        let input_vec = vec![
+            ("1a + 2b + 3", vec![0, 5], 3),
+            ("12a", vec![0], 0),
+            ("1 + 2", vec![], 3),
+            ("x.1 + 1e + 3 * 4.4.4", vec![0, 6, 15], 4),
+        ];
+        for (input, positions, count) in input_vec {
+            let (tokens, errors) = tokenization_recovering(input);
+            let error_positions = errors.iter().map(|e| e.position).collect::<Vec<_>>();
+            assert_eq!(error_positions, positions, "input: {}", input);
+            assert_eq!(tokens.len(), count, "input: {}", input);
+        }
+    }
 }
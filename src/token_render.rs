@@ -1,5 +1,6 @@
 use crate::parse::ParseErr;
 use crate::parse::ParseErrType;
+use crate::parse_trace::ParseTrace;
 use crate::token::*;
 
 /**
@@ -7,11 +8,13 @@ TokenRender
 
 储存词法分析的结果的结构。
 
-提供相应接口方便语法分析。
+提供相应接口方便语法分析。开启跟踪后还会记录语法分析的过程。
  */
 pub struct TokenRender {
     tokens: Vec<Token>,
     current_position: usize,
+    errors: Vec<ParseErr>,
+    trace: Option<ParseTrace>,
 }
 
 impl TokenRender {
@@ -22,6 +25,8 @@ impl TokenRender {
         return TokenRender {
             tokens,
             current_position: 0,
+            errors: vec![],
+            trace: None,
         };
     }
 
@@ -36,10 +41,35 @@ impl TokenRender {
         return &self.tokens[self.current_position];
     }
 
+    /**
+    返回下一个 token 之后第 `offset` 个 token ，`peek_at(0)` 就是 `peek()` 。
+
+    超出范围时返回 `None` 。
+
+    */
+    pub fn peek_at(&self, offset: usize) -> Option<&Token> {
+        return self.tokens.get(self.current_position + offset);
+    }
+
+    /**
+    返回上一个被消费的 token 。
+
+    如果还没有消费过 token 的话会 `panic`
+
+    */
+    pub fn last(&self) -> &Token {
+        assert!(self.current_position > 0);
+        return &self.tokens[self.current_position - 1];
+    }
+
     /**
     消费一个 token 。
      */
     pub fn next(&mut self) {
+        if let Some(trace) = &mut self.trace {
+            let token = &self.tokens[self.current_position];
+            trace.terminal(&token.original_str, token.span());
+        }
         self.current_position += 1;
     }
 
@@ -52,7 +82,7 @@ impl TokenRender {
 
     # Examples
 
-    ```
+    ```ignore
     render.expect(
         TokenInfo::Symbol(SymbolType::RightBracket),
         |token| {
@@ -76,6 +106,11 @@ impl TokenRender {
                         return Ok(token.to_owned());
                     }
                 }
+                TokenInfo::Identifier(_) => {
+                    if let TokenInfo::Identifier(_) = token.info {
+                        return Ok(token.to_owned());
+                    }
+                }
                 TokenInfo::Symbol(rhs) => {
                     if let TokenInfo::Symbol(lhs) = &token.info {
                         if rhs == *lhs {
@@ -99,7 +134,7 @@ impl TokenRender {
     /**
     代替以下代码：
 
-    ```
+    ```ignore
     if token_info == self.peek().info {
         self.next();
         return true;
@@ -118,6 +153,12 @@ impl TokenRender {
                         return true;
                     }
                 }
+                TokenInfo::Identifier(_) => {
+                    if let TokenInfo::Identifier(_) = token.info {
+                        self.next();
+                        return true;
+                    }
+                }
                 TokenInfo::Symbol(rhs) => {
                     if let TokenInfo::Symbol(lhs) = &token.info {
                         if rhs == *lhs {
@@ -131,6 +172,70 @@ impl TokenRender {
         return false;
     }
 
+    /**
+    记下一个语法错误，用于出错后继续解析。
+
+    同一个位置只记第一个错误，避免一个错误在不同的层次被重复报告。
+    */
+    pub fn report(&mut self, err: ParseErr) {
+        let duplicated = self
+            .errors
+            .iter()
+            .any(|e| match (&e.err_type, &err.err_type) {
+                (ParseErrType::Unexpected(lhs), ParseErrType::Unexpected(rhs)) => {
+                    lhs.position == rhs.position
+                }
+                (ParseErrType::Insufficient, ParseErrType::Insufficient) => true,
+                _ => false,
+            });
+
+        if !duplicated {
+            self.errors.push(err);
+        }
+    }
+
+    /** 取出所有记下的语法错误，按发现的顺序排列。 */
+    pub fn take_errors(&mut self) -> Vec<ParseErr> {
+        return std::mem::take(&mut self.errors);
+    }
+
+    /** 开启跟踪，之后的语法分析会记录进入和离开每个非终结符，以及消费的 token 。 */
+    pub fn enable_trace(&mut self) {
+        self.trace = Some(ParseTrace::new());
+    }
+
+    /** 取出跟踪记录，没有开启跟踪时返回 `None` ，取出后不再跟踪。 */
+    pub fn take_trace(&mut self) -> Option<ParseTrace> {
+        return self.trace.take();
+    }
+
+    /** 开启了跟踪时记下进入非终结符 `rule` 。 */
+    pub fn enter(&mut self, rule: &'static str) {
+        if self.trace.is_none() {
+            return;
+        }
+        let token = self.peek_text();
+        if let Some(trace) = &mut self.trace {
+            trace.enter(rule, token);
+        }
+    }
+
+    /** 开启了跟踪时记下离开最近进入的非终结符，`success` 表示是否成功解析。 */
+    pub fn exit(&mut self, success: bool) {
+        if self.trace.is_none() {
+            return;
+        }
+        let token = self.peek_text();
+        if let Some(trace) = &mut self.trace {
+            trace.exit(success, token);
+        }
+    }
+
+    /// 下一个 token 的原文本，已经为空时返回 `None`
+    fn peek_text(&self) -> Option<String> {
+        return self.peek_at(0).map(|token| token.original_str.clone());
+    }
+
     /** 检查 TokenRender 是否已经为空。 */
     pub fn is_empty(&self) -> bool {
         if self.current_position >= self.tokens.len() {